@@ -1,28 +1,195 @@
 //! # Advent of Code 2021 - Day 4
 //!
 //! This module contains the solution of the [fourth day's challenges](https://adventofcode.com/2021/day/4).
-use ndarray::Array2;
-use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug)]
-struct Board {
-    board: Array2<usize>,
-    marked: RefCell<Array2<usize>>,
+/// The largest supported board size, such that all cells fit into a `u128` bitmask.
+///
+/// Boards with more rows are rejected with `ParseBingoError::TooLarge`.
+pub const MAX_SIZE: usize = 11;
+
+/// The errors that can occur while parsing bingo boards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseBingoError {
+    /// The input does not contain any line with called numbers.
+    MissingNumbers,
+    /// A called number or a board entry is not a number.
+    InvalidNumber(String),
+    /// The board with the given index does not have as many entries in each row as it has rows.
+    NotSquare { board: usize },
+    /// The board with the given index is larger than `MAX_SIZE`.
+    TooLarge { board: usize, size: usize },
+}
+
+impl fmt::Display for ParseBingoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseBingoError::MissingNumbers => write!(f, "no called numbers in the input"),
+            ParseBingoError::InvalidNumber(s) => write!(f, "invalid number {:?}", s),
+            ParseBingoError::NotSquare { board } => write!(f, "board {} is not square", board),
+            ParseBingoError::TooLarge { board, size } => write!(
+                f,
+                "board {} has {} rows, but boards may have at most {} rows",
+                board, size, MAX_SIZE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseBingoError {}
+
+/// Parse a single number of the input.
+fn parse_number(s: &str) -> Result<usize, ParseBingoError> {
+    s.trim()
+        .parse()
+        .map_err(|_| ParseBingoError::InvalidNumber(s.to_string()))
+}
+
+/// A completely marked line on a board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line {
+    Row(usize),
+    Column(usize),
+    /// The diagonal from the top left to the bottom right corner.
+    Diagonal,
+    /// The diagonal from the top right to the bottom left corner.
+    AntiDiagonal,
+}
+
+/// A square bingo board with at most `MAX_SIZE` rows and columns.
+///
+/// The marked cells are tracked in a bitmask, where the cell in row `r` and column `c`
+/// corresponds to bit `r * size + c`. In order to mark a number in constant time, the board
+/// keeps an index from its numbers to their cells, the sum of the unmarked numbers and the
+/// number of marked cells in each line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Board {
+    size: usize,
+    numbers: Vec<usize>,
+    marked: u128,
+    /// The cells containing each number.
+    cells: HashMap<usize, Vec<usize>>,
+    unmarked_sum: usize,
+    row_hits: Vec<usize>,
+    column_hits: Vec<usize>,
+    /// The marked cells on the diagonal and the anti-diagonal, `None` if diagonals do not count.
+    diagonal_hits: Option<[usize; 2]>,
+    /// The first line that has been completed.
+    winning_line: Option<Line>,
 }
 
 impl Board {
     /// Construct a board from the string representation of each row.
-    fn from_string_rows(string_rows: &[String]) -> Self {
-        let marked = RefCell::new(Array2::from_elem((5, 5), 0));
-        let mut board = Array2::from_elem((5, 5), 0);
-        for (row, string_row) in string_rows.iter().enumerate() {
-            string_row
+    ///
+    /// The size of the board is given by the number of rows, which may not exceed `MAX_SIZE`.
+    /// If `diagonals` is set, completing one of the two diagonals also wins.
+    pub fn from_string_rows(
+        string_rows: &[String],
+        diagonals: bool,
+    ) -> Result<Self, ParseBingoError> {
+        let size = string_rows.len();
+        if size > MAX_SIZE {
+            return Err(ParseBingoError::TooLarge { board: 0, size });
+        }
+
+        let mut numbers = Vec::with_capacity(size * size);
+        for string_row in string_rows {
+            let row = string_row
                 .split_whitespace()
-                .enumerate()
-                .map(|(col, n)| (col, n.parse::<usize>().unwrap()))
-                .for_each(|(col, n)| board[[row, col]] = n);
+                .map(parse_number)
+                .collect::<Result<Vec<_>, _>>()?;
+            if row.len() != size {
+                return Err(ParseBingoError::NotSquare { board: 0 });
+            }
+            numbers.extend(row);
+        }
+
+        let mut cells = HashMap::new();
+        for (cell, number) in numbers.iter().enumerate() {
+            cells.entry(*number).or_insert_with(Vec::new).push(cell);
+        }
+
+        Ok(Self {
+            size,
+            unmarked_sum: numbers.iter().sum(),
+            numbers,
+            marked: 0,
+            cells,
+            row_hits: vec![0; size],
+            column_hits: vec![0; size],
+            diagonal_hits: if diagonals { Some([0, 0]) } else { None },
+            winning_line: None,
+        })
+    }
+
+    /// The number of rows and columns of the board.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The number in row `row` and column `col`.
+    pub fn number(&self, row: usize, col: usize) -> usize {
+        self.numbers[row * self.size + col]
+    }
+
+    /// Check whether the cell in row `row` and column `col` has been marked.
+    pub fn is_marked(&self, row: usize, col: usize) -> bool {
+        self.marked >> (row * self.size + col) & 1 == 1
+    }
+
+    /// The sum of all numbers that have not been marked.
+    pub fn unmarked_sum(&self) -> usize {
+        self.unmarked_sum
+    }
+
+    /// Check whether a row, column or enabled diagonal is completely marked.
+    pub fn has_won(&self) -> bool {
+        self.winning_line.is_some()
+    }
+
+    /// The first line that has been completely marked.
+    ///
+    /// If a single number completes several lines, rows take precedence over columns and
+    /// columns over diagonals.
+    pub fn winning_line(&self) -> Option<Line> {
+        self.winning_line
+    }
+
+    /// Mark the cell with index `cell` and record the first completed line.
+    fn mark_cell(&mut self, cell: usize) {
+        if self.marked >> cell & 1 == 1 {
+            return;
+        }
+        self.marked |= 1 << cell;
+        self.unmarked_sum -= self.numbers[cell];
+
+        let (row, col) = (cell / self.size, cell % self.size);
+        self.row_hits[row] += 1;
+        self.column_hits[col] += 1;
+        // Keep the first completed line in order of precedence
+        let mut completed = None;
+        if self.row_hits[row] == self.size {
+            completed = completed.or(Some(Line::Row(row)));
+        }
+        if self.column_hits[col] == self.size {
+            completed = completed.or(Some(Line::Column(col)));
         }
-        Self { board, marked }
+        if let Some(hits) = self.diagonal_hits.as_mut() {
+            if row == col {
+                hits[0] += 1;
+                if hits[0] == self.size {
+                    completed = completed.or(Some(Line::Diagonal));
+                }
+            }
+            if row + col == self.size - 1 {
+                hits[1] += 1;
+                if hits[1] == self.size {
+                    completed = completed.or(Some(Line::AntiDiagonal));
+                }
+            }
+        }
+        self.winning_line = self.winning_line.or(completed);
     }
 
     /// Add a new number to the board.
@@ -31,44 +198,178 @@ impl Board {
     /// drawn numbers, `None` is returned. If at least one such row or column exists, the product of the
     /// sum of the values on the board which do not match a drawn number and the last added drawn number
     /// is returned.
-    pub fn mark_number(&self, number: usize) -> Option<usize> {
-        // For each number, look for hits on the board and compute the sum of board
-        // entries not associated to hits
-        let mut sum = 0;
-        for row in 0..5 {
-            for col in 0..5 {
-                if self.board[[row, col]] == number {
-                    self.marked.borrow_mut()[[row, col]] = 1;
-                }
-                if self.marked.borrow_mut()[[row, col]] == 0 {
-                    sum += self.board[[row, col]];
-                }
-            }
-        }
-        // Check if there is any row or column where all entries are true. If this is the case, return the sum
-        for row in self.marked.borrow().rows() {
-            if row.iter().sum::<usize>() == 5 {
-                return Some(sum * number);
+    pub fn mark_number(&mut self, number: usize) -> Option<usize> {
+        if let Some(cells) = self.cells.get_mut(&number) {
+            // Move the cells out temporarily to mark them without cloning
+            let cells = std::mem::take(cells);
+            for &cell in &cells {
+                self.mark_cell(cell);
             }
+            self.cells.insert(number, cells);
         }
 
-        for col in self.marked.borrow().columns() {
-            if col.iter().sum::<usize>() == 5 {
-                return Some(sum * number);
+        self.winning_line.map(|_| self.unmarked_sum * number)
+    }
+}
+
+/// A game of bingo: the numbers that are called and the boards that are played.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BingoGame {
+    pub numbers: Vec<usize>,
+    pub boards: Vec<Board>,
+}
+
+impl BingoGame {
+    /// Construct the game from the lines of the input.
+    ///
+    /// The first line contains the comma-separated numbers to be called, followed by the
+    /// boards, which are separated by empty lines. The size of each board is inferred from
+    /// its number of rows.
+    pub fn from_input(input: &[String], diagonals: bool) -> Result<Self, ParseBingoError> {
+        let numbers = input
+            .first()
+            .ok_or(ParseBingoError::MissingNumbers)?
+            .split(',')
+            .map(parse_number)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let boards = input[1..]
+            .split(|line| line.trim().is_empty())
+            .filter(|rows| !rows.is_empty())
+            .enumerate()
+            .map(|(board, rows)| {
+                Board::from_string_rows(rows, diagonals).map_err(|e| match e {
+                    ParseBingoError::NotSquare { .. } => ParseBingoError::NotSquare { board },
+                    ParseBingoError::TooLarge { size, .. } => {
+                        ParseBingoError::TooLarge { board, size }
+                    }
+                    e => e,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { numbers, boards })
+    }
+}
+
+/// The win of a board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WinEvent {
+    /// The index of the winning board.
+    pub board: usize,
+    /// The number that completed the winning line.
+    pub number: usize,
+    /// The number of numbers called until the win, i.e. the first number is called in turn 1.
+    pub turn: usize,
+    /// The product of the sum of the unmarked numbers and the winning number.
+    pub score: usize,
+    pub line: Line,
+}
+
+/// An iterator over the wins of the boards in a game, in the order in which they occur.
+///
+/// Boards winning with the same number are reported in the order of their indices. Once the
+/// iterator is exhausted, `never_won` returns the boards that did not win at all.
+pub struct Wins {
+    game: BingoGame,
+    won: Vec<bool>,
+    /// The indices of the boards containing each number.
+    boards_with_number: HashMap<usize, Vec<usize>>,
+    /// The index of the number that is currently called.
+    number_index: usize,
+    /// The position in the boards containing the current number to continue marking at.
+    board_index: usize,
+}
+
+impl Wins {
+    /// The indices of the boards that have not won so far.
+    pub fn never_won(&self) -> Vec<usize> {
+        (0..self.won.len()).filter(|i| !self.won[*i]).collect()
+    }
+}
+
+impl Iterator for Wins {
+    type Item = WinEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.number_index < self.game.numbers.len() {
+            let number = self.game.numbers[self.number_index];
+            let candidates = self
+                .boards_with_number
+                .get(&number)
+                .map_or(&[][..], |boards| &boards[..]);
+            while self.board_index < candidates.len() {
+                let board_index = candidates[self.board_index];
+                self.board_index += 1;
+                if self.won[board_index] {
+                    continue;
+                }
+                let board = &mut self.game.boards[board_index];
+                if let Some(score) = board.mark_number(number) {
+                    self.won[board_index] = true;
+                    return Some(WinEvent {
+                        board: board_index,
+                        number,
+                        turn: self.number_index + 1,
+                        score,
+                        line: board.winning_line().unwrap(),
+                    });
+                }
             }
+            self.number_index += 1;
+            self.board_index = 0;
         }
-
         None
     }
 }
 
-/// Extract a vector of `Board`s from the input
-fn get_boards_from_input(input: &[String]) -> Vec<Board> {
-    input[2..]
-        .chunks(6)
-        .map(|chunk| chunk.iter().cloned().take(5).collect::<Vec<String>>())
-        .map(|v| Board::from_string_rows(&v))
-        .collect()
+/// The boards of a game ordered by the time of their win.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ranking {
+    /// The wins in the order in which they occur.
+    pub wins: Vec<WinEvent>,
+    /// The indices of the boards that never win.
+    pub never_won: Vec<usize>,
+}
+
+impl Ranking {
+    /// The win of the board finishing at `rank`, where rank 0 is the first winner.
+    pub fn rank(&self, rank: usize) -> Option<&WinEvent> {
+        self.wins.get(rank)
+    }
+}
+
+impl BingoGame {
+    /// Play the game and iterate over the wins of the boards.
+    ///
+    /// Each called number is only marked on the boards that contain it.
+    pub fn wins(self) -> Wins {
+        let mut boards_with_number = HashMap::new();
+        for (index, board) in self.boards.iter().enumerate() {
+            for number in board.cells.keys() {
+                boards_with_number
+                    .entry(*number)
+                    .or_insert_with(Vec::new)
+                    .push(index);
+            }
+        }
+        Wins {
+            won: vec![false; self.boards.len()],
+            boards_with_number,
+            game: self,
+            number_index: 0,
+            board_index: 0,
+        }
+    }
+
+    /// Play the game until all numbers are called and rank the boards.
+    pub fn ranking(self) -> Ranking {
+        let mut wins = self.wins();
+        Ranking {
+            wins: wins.by_ref().collect(),
+            never_won: wins.never_won(),
+        }
+    }
 }
 
 /// Find the winning board.
@@ -78,56 +379,21 @@ fn get_boards_from_input(input: &[String]) -> Vec<Board> {
 /// As soon as one board wins, the product of the sum of its unmarked numbers and
 /// the last called number that lead to the win is returned.
 pub fn day_4_1(string_rows: &[String]) -> usize {
-    let boards: Vec<Board> = get_boards_from_input(string_rows);
-
-    for called_number in string_rows[0]
-        .split(',')
-        .map(|s| s.parse::<usize>().unwrap())
-    {
-        for board in boards.iter() {
-            match board.mark_number(called_number) {
-                None => continue,
-                Some(result) => return result,
-            }
-        }
-    }
-
-    panic!("No board won!");
+    let game = BingoGame::from_input(string_rows, false).unwrap();
+    game.wins().next().expect("No board won!").score
 }
 
 /// Find the board that wins last.
 ///
 /// The function takes the lines from the input and constructs the bingo boards,
 /// After that, the called numbers are marked on the bingo boards, one after the other.
-/// As soon as one board wins, the product of the sum of its unmarked numbers and
-/// the last called number that lead to the win is returned. We repeat until, we find the last board.
+/// The score of the last board to win is returned.
 pub fn day_4_2(string_rows: &[String]) -> usize {
-    // First collect all the boards
-    let boards: Vec<Board> = get_boards_from_input(string_rows);
-
-    let mut boards_in_game = vec![true; boards.len()];
-
-    for called_number in string_rows[0]
-        .split(',')
-        .map(|s| s.parse::<usize>().unwrap())
-    {
-        for (board_index, board) in boards.iter().enumerate() {
-            if !boards_in_game[board_index] {
-                continue;
-            }
-            match board.mark_number(called_number) {
-                None => continue,
-                Some(result) => {
-                    boards_in_game[board_index] = false;
-                    if boards_in_game.iter().all(|b| !b) {
-                        return result;
-                    }
-                }
-            }
-        }
+    let ranking = BingoGame::from_input(string_rows, false).unwrap().ranking();
+    if !ranking.never_won.is_empty() {
+        panic!("Not all boards won!");
     }
-
-    panic!("Not all boards won!");
+    ranking.wins.last().expect("No board won!").score
 }
 
 #[cfg(test)]
@@ -144,7 +410,7 @@ mod tests {
             " 2  0 12  3  7".to_string(),
         ];
 
-        let board = Board::from_string_rows(&rows);
+        let mut board = Board::from_string_rows(&rows, false).unwrap();
         assert_eq!(board.mark_number(7), None);
         assert_eq!(board.mark_number(4), None);
         assert_eq!(board.mark_number(9), None);
@@ -212,4 +478,122 @@ mod tests {
 
         assert_eq!(day_4_2(&input), 1924);
     }
+
+    #[test]
+    fn test_board_sizes() {
+        let input = vec![
+            "1,5,9,2".to_string(),
+            "".to_string(),
+            "1 2 3".to_string(),
+            "4 5 6".to_string(),
+            "7 8 9".to_string(),
+            "".to_string(),
+            "1 2".to_string(),
+            "3 4".to_string(),
+        ];
+        let game = BingoGame::from_input(&input, false).unwrap();
+        assert_eq!(game.numbers, vec![1, 5, 9, 2]);
+        assert_eq!(game.boards.len(), 2);
+        assert_eq!(game.boards[0].size(), 3);
+        assert_eq!(game.boards[1].size(), 2);
+        assert_eq!(game.boards[0].number(1, 2), 6);
+
+        // The second board wins with its first row
+        assert_eq!(day_4_1(&input), 7 * 2);
+
+        let mut input = input;
+        input[4] = "7 8".to_string();
+        assert_eq!(
+            BingoGame::from_input(&input, false),
+            Err(ParseBingoError::NotSquare { board: 0 })
+        );
+        input[0] = "1,x".to_string();
+        assert_eq!(
+            BingoGame::from_input(&input, false),
+            Err(ParseBingoError::InvalidNumber("x".to_string()))
+        );
+
+        let rows = |size: usize| {
+            let row = (1..=size).map(|n| n.to_string()).collect::<Vec<_>>();
+            vec![row.join(" "); size]
+        };
+        assert!(Board::from_string_rows(&rows(MAX_SIZE), false).is_ok());
+        let error = Board::from_string_rows(&rows(MAX_SIZE + 1), false).unwrap_err();
+        assert_eq!(
+            error,
+            ParseBingoError::TooLarge {
+                board: 0,
+                size: MAX_SIZE + 1
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "board 0 has 12 rows, but boards may have at most 11 rows"
+        );
+    }
+
+    #[test]
+    fn test_diagonals() {
+        let rows = vec![
+            "1 2 3".to_string(),
+            "4 5 6".to_string(),
+            "7 8 9".to_string(),
+        ];
+
+        let mut board = Board::from_string_rows(&rows, true).unwrap();
+        assert_eq!(board.mark_number(3), None);
+        assert_eq!(board.mark_number(5), None);
+        assert_eq!(board.mark_number(7), Some((1 + 2 + 4 + 6 + 8 + 9) * 7));
+        assert!(board.is_marked(1, 1));
+        assert!(!board.is_marked(1, 0));
+
+        let mut board = Board::from_string_rows(&rows, false).unwrap();
+        assert_eq!(board.mark_number(3), None);
+        assert_eq!(board.mark_number(5), None);
+        assert_eq!(board.mark_number(7), None);
+        assert!(!board.has_won());
+    }
+
+    #[test]
+    fn test_ranking() {
+        let input = vec![
+            "1,2,3,4,9".to_string(),
+            "".to_string(),
+            "1 2".to_string(),
+            "3 4".to_string(),
+            "".to_string(),
+            "5 6".to_string(),
+            "7 8".to_string(),
+            "".to_string(),
+            "4 3".to_string(),
+            "2 1".to_string(),
+        ];
+        let ranking = BingoGame::from_input(&input, false).unwrap().ranking();
+        assert_eq!(
+            ranking.wins,
+            vec![
+                WinEvent {
+                    board: 0,
+                    number: 2,
+                    turn: 2,
+                    score: 7 * 2,
+                    line: Line::Row(0)
+                },
+                WinEvent {
+                    board: 2,
+                    number: 2,
+                    turn: 2,
+                    score: 7 * 2,
+                    line: Line::Row(1)
+                },
+            ]
+        );
+        assert_eq!(ranking.never_won, vec![1]);
+        assert_eq!(ranking.rank(1).map(|w| w.board), Some(2));
+        assert_eq!(ranking.rank(2), None);
+
+        let mut wins = BingoGame::from_input(&input, false).unwrap().wins();
+        assert_eq!(wins.next().map(|w| w.board), Some(0));
+        assert_eq!(wins.never_won(), vec![1, 2]);
+    }
 }
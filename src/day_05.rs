@@ -1,89 +1,465 @@
 //! # Advent of Code 2021 - Day 5
 //!
 //! This module contains the solution of the [fifth day's challenges](https://adventofcode.com/2021/day/5).
+use itertools::Itertools;
 use ndarray::Array2;
 use regex::Regex;
-use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::fmt;
 
 lazy_static::lazy_static! {
-    static ref RE: Regex = Regex::new(r"^(\d*),(\d*) -> (\d*),(\d*)$").unwrap();
+    static ref RE: Regex = Regex::new(r"^\s*([+-]?\d+)\s*,\s*([+-]?\d+)\s*->\s*([+-]?\d+)\s*,\s*([+-]?\d+)\s*$").unwrap();
 }
 
-type Line = ((usize, usize), (usize, usize));
+/// A point in the cartesian plane.
+pub type Point = (isize, isize);
+
+/// A line segment from its first to its second point.
+pub type Line = (Point, Point);
+
+/// The error returned when a line of the input cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseLineError {
+    /// The line does not have the form `x1,y1 -> x2,y2`.
+    InvalidFormat(String),
+    /// A coordinate does not fit into an `isize`.
+    InvalidNumber(String),
+}
+
+impl fmt::Display for ParseLineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseLineError::InvalidFormat(s) => write!(f, "invalid line {:?}", s),
+            ParseLineError::InvalidNumber(s) => write!(f, "invalid coordinate {:?}", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseLineError {}
 
 /// Get the line coordinates from the string representation in the input line.
 ///
 /// The string representation
 ///
 /// ```sh
-/// 1,2 -> 33,44
+/// 1,-2 -> 33,44
 /// ```
 ///
 /// will be mapped to the tuple
 ///
 /// ```sh
-/// ((1, 2),(33, 44))
+/// ((1, -2),(33, 44))
 /// ```
-fn get_coordinates(s: &str) -> Line {
-    let captures = RE.captures(s).unwrap();
-    (
-        (
-            captures.get(1).unwrap().as_str().parse::<usize>().unwrap(),
-            captures.get(2).unwrap().as_str().parse::<usize>().unwrap(),
-        ),
-        (
-            captures.get(3).unwrap().as_str().parse::<usize>().unwrap(),
-            captures.get(4).unwrap().as_str().parse::<usize>().unwrap(),
-        ),
-    )
+///
+/// Whitespace around the line and between numbers and separators is ignored.
+pub fn get_coordinates(s: &str) -> Result<Line, ParseLineError> {
+    let captures = RE
+        .captures(s)
+        .ok_or_else(|| ParseLineError::InvalidFormat(s.to_string()))?;
+    let coordinate = |i: usize| {
+        let coordinate = captures.get(i).unwrap().as_str();
+        coordinate
+            .parse::<isize>()
+            .map_err(|_| ParseLineError::InvalidNumber(coordinate.to_string()))
+    };
+    Ok((
+        (coordinate(1)?, coordinate(2)?),
+        (coordinate(3)?, coordinate(4)?),
+    ))
 }
 
-/// Number of points in the cartesian plane where more than one horizontal or vertical segments pass.
-/// If `include_diagonal=true` then also diagonals are included.
-pub fn day_5(data: &[String], include_diagonal: bool) -> usize {
-    // parse the input
-    let lines: Vec<Line> = data.iter().map(|s| get_coordinates(s)).collect();
-    // determine the dimensions of the grid
-    let x_max = *lines
-        .iter()
-        .map(|((x1, _), (x2, _))| max(x1, x2))
-        .max()
-        .unwrap();
-    let y_max = *lines
-        .iter()
-        .map(|((_, y1), (_, y2))| max(y1, y2))
-        .max()
-        .unwrap();
-    // create the grid (+1, because 0 is included at the beginning)
-    let mut grid = Array2::from_elem((y_max + 1, x_max + 1), 0);
+/// Parse all lines of the input.
+pub fn parse_lines(data: &[String]) -> Result<Vec<Line>, ParseLineError> {
+    data.iter().map(|s| get_coordinates(s)).collect()
+}
+
+/// The lines whose points are counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rasterization {
+    /// Only horizontal and vertical lines.
+    AxisAligned,
+    /// Horizontal, vertical and diagonal lines at 45 degrees.
+    Diagonal,
+    /// Lines at arbitrary angles, of which the points with integer coordinates are counted.
+    Arbitrary,
+}
+
+/// The greatest common divisor of `a` and `b`.
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// The number of steps between neighboring points with integer coordinates on a line.
+///
+/// A line from `(x1, y1)` to `(x2, y2)` passes through `gcd(|x2 - x1|, |y2 - y1|) + 1` such
+/// points. Single points have zero steps.
+fn lattice_steps(line: &Line) -> usize {
+    let ((x1, y1), (x2, y2)) = *line;
+    gcd(x1.abs_diff(x2), y1.abs_diff(y2))
+}
+
+/// The points with integer coordinates on a line, from its start to its end.
+///
+/// For lines that are not considered by the `rasterization`, no points are returned. Single
+/// points are considered by all rasterizations.
+fn points(line: &Line, rasterization: Rasterization) -> impl Iterator<Item = Point> {
+    let ((x1, y1), (x2, y2)) = *line;
+    let dx = x2 as i128 - x1 as i128;
+    let dy = y2 as i128 - y1 as i128;
+    let considered = match rasterization {
+        Rasterization::AxisAligned => dx == 0 || dy == 0,
+        Rasterization::Diagonal => dx == 0 || dy == 0 || dx.abs() == dy.abs(),
+        Rasterization::Arbitrary => true,
+    };
+    let steps = lattice_steps(line) as i128;
+    // A single point is its own step
+    let (x_step, y_step) = if steps == 0 {
+        (0, 0)
+    } else {
+        (dx / steps, dy / steps)
+    };
+    (0..=steps)
+        .take(if considered { steps as usize + 1 } else { 0 })
+        .map(move |i| {
+            (
+                (x1 as i128 + i * x_step) as isize,
+                (y1 as i128 + i * y_step) as isize,
+            )
+        })
+}
+
+/// The number of lines passing through each point, on a dense grid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeatMap {
+    /// The point corresponding to the entry `[[0, 0]]` of `counts`.
+    pub origin: Point,
+    /// The counts, indexed by `[[y - origin.1, x - origin.0]]`.
+    pub counts: Array2<usize>,
+}
+
+impl HeatMap {
+    /// The number of lines passing through the point `(x, y)`.
+    pub fn count(&self, (x, y): Point) -> usize {
+        let offset = |value: isize, origin: isize| {
+            value
+                .checked_sub(origin)
+                .and_then(|offset| usize::try_from(offset).ok())
+        };
+        offset(x, self.origin.0)
+            .zip(offset(y, self.origin.1))
+            .and_then(|(col, row)| self.counts.get([row, col]))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// The maximum number of cells of a heat map.
+pub const MAX_HEAT_MAP_CELLS: usize = 1 << 24;
+
+/// The error returned when the bounding box of the lines has more than
+/// `MAX_HEAT_MAP_CELLS` cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeatMapTooLarge {
+    /// The width of the bounding box, saturating at `usize::MAX`.
+    pub width: usize,
+    /// The height of the bounding box, saturating at `usize::MAX`.
+    pub height: usize,
+}
+
+impl fmt::Display for HeatMapTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "heat map of {}x{} cells exceeds the limit of {} cells",
+            self.width, self.height, MAX_HEAT_MAP_CELLS
+        )
+    }
+}
+
+impl std::error::Error for HeatMapTooLarge {}
+
+/// Compute the heat map of the lines considered by the `rasterization`.
+///
+/// The heat map covers the bounding box of all lines, i.e. it is offset by the smallest
+/// coordinates. Use `overlap_points` for lines spanning more than `MAX_HEAT_MAP_CELLS` cells.
+pub fn heat_map(lines: &[Line], rasterization: Rasterization) -> Result<HeatMap, HeatMapTooLarge> {
+    let xs = || lines.iter().flat_map(|((x1, _), (x2, _))| [*x1, *x2]);
+    let ys = || lines.iter().flat_map(|((_, y1), (_, y2))| [*y1, *y2]);
+    let origin = (xs().min().unwrap_or(0), ys().min().unwrap_or(0));
+    let x_max = xs().max().unwrap_or(0);
+    let y_max = ys().max().unwrap_or(0);
+    // both bounds are included
+    let width = x_max.abs_diff(origin.0).saturating_add(1);
+    let height = y_max.abs_diff(origin.1).saturating_add(1);
+    if width.saturating_mul(height) > MAX_HEAT_MAP_CELLS {
+        return Err(HeatMapTooLarge { width, height });
+    }
+    // create the grid
+    let mut counts = Array2::from_elem((height, width), 0);
     // update the grid
     for line in lines {
-        let ((x1, y1), (x2, y2)) = line;
-        if x1 == x2 {
-            for y in min(y1, y2)..=max(y1, y2) {
-                grid[[y, x1]] += 1;
-            }
-        } else if y1 == y2 {
-            for x in min(x1, x2)..=max(x1, x2) {
-                grid[[y1, x]] += 1;
+        for (x, y) in points(line, rasterization) {
+            counts[[y.abs_diff(origin.1), x.abs_diff(origin.0)]] += 1;
+        }
+    }
+    Ok(HeatMap { origin, counts })
+}
+
+/// Count the points where at least two lines overlap using a dense grid.
+fn count_overlaps_dense(
+    lines: &[Line],
+    rasterization: Rasterization,
+) -> Result<usize, HeatMapTooLarge> {
+    // check number of entries higher than 2
+    Ok(heat_map(lines, rasterization)?
+        .counts
+        .iter()
+        .filter(|count| count > &&1)
+        .count())
+}
+
+/// Count the number of lines considered by the `rasterization` passing through each point.
+///
+/// Points without any line are not contained in the result.
+fn point_counts(lines: &[Line], rasterization: Rasterization) -> HashMap<Point, usize> {
+    let mut counts: HashMap<Point, usize> = HashMap::new();
+    for line in lines {
+        for point in points(line, rasterization) {
+            *counts.entry(point).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Count the points where at least two lines overlap by hashing the covered points.
+///
+/// The memory required only depends on the number of covered points, not on the size of the
+/// area spanned by the lines.
+fn count_overlaps_sparse(lines: &[Line], rasterization: Rasterization) -> usize {
+    point_counts(lines, rasterization)
+        .values()
+        .filter(|count| **count > 1)
+        .count()
+}
+
+/// List the points where at least two lines considered by the `rasterization` overlap, along
+/// with the number of lines passing through them.
+///
+/// The points are ordered by their y and then by their x coordinate.
+pub fn overlap_points(lines: &[Line], rasterization: Rasterization) -> Vec<(Point, usize)> {
+    let mut points = point_counts(lines, rasterization)
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .collect::<Vec<_>>();
+    points.sort_unstable_by_key(|((x, y), _)| (*y, *x));
+    points
+}
+
+/// How two line segments intersect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Intersection {
+    /// The segments cross or touch in a single point, which need not have integer coordinates.
+    Point(f64, f64),
+    /// The segments are collinear and share the segment between the two points.
+    Overlap(Point, Point),
+}
+
+/// The intersection of the segments with indices `first < second`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SegmentIntersection {
+    pub first: usize,
+    pub second: usize,
+    pub intersection: Intersection,
+}
+
+/// The error returned when the intersection of two segments cannot be computed exactly,
+/// because the products of their coordinates do not fit into an `i128`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntersectionOverflow {
+    pub first: usize,
+    pub second: usize,
+}
+
+impl fmt::Display for IntersectionOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "intersection of segments {} and {} overflows",
+            self.first, self.second
+        )
+    }
+}
+
+impl std::error::Error for IntersectionOverflow {}
+
+/// The overflow of an intermediate result while intersecting two segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Overflow;
+
+/// The vector from `a` to `b`.
+fn difference(a: Point, b: Point) -> (i128, i128) {
+    (b.0 as i128 - a.0 as i128, b.1 as i128 - a.1 as i128)
+}
+
+/// The z-component of the cross product of two vectors.
+fn cross(u: (i128, i128), v: (i128, i128)) -> Result<i128, Overflow> {
+    u.0.checked_mul(v.1)
+        .zip(u.1.checked_mul(v.0))
+        .and_then(|(a, b)| a.checked_sub(b))
+        .ok_or(Overflow)
+}
+
+/// The dot product of two vectors.
+fn dot(u: (i128, i128), v: (i128, i128)) -> Result<i128, Overflow> {
+    u.0.checked_mul(v.0)
+        .zip(u.1.checked_mul(v.1))
+        .and_then(|(a, b)| a.checked_add(b))
+        .ok_or(Overflow)
+}
+
+/// Check whether the point `p` lies on the segment from `a` to `b`.
+fn on_segment(p: Point, (a, b): Line) -> Result<bool, Overflow> {
+    Ok(cross(difference(a, b), difference(a, p))? == 0
+        && p.0 >= a.0.min(b.0)
+        && p.0 <= a.0.max(b.0)
+        && p.1 >= a.1.min(b.1)
+        && p.1 <= a.1.max(b.1))
+}
+
+/// Compute how two segments intersect, if at all.
+fn intersect(a: Line, b: Line) -> Result<Option<Intersection>, Overflow> {
+    let r = difference(a.0, a.1);
+    let s = difference(b.0, b.1);
+    let qp = difference(a.0, b.0);
+    let denominator = cross(r, s)?;
+
+    if denominator == 0 {
+        if cross(qp, r)? != 0 || cross(qp, s)? != 0 {
+            // Parallel, but not on the same line
+            return Ok(None);
+        }
+        // Collinear or degenerate to single points: the shared part is bounded by endpoints
+        let direction = if r != (0, 0) { r } else { s };
+        let mut shared = vec![];
+        for (p, other) in [(a.0, b), (a.1, b), (b.0, a), (b.1, a)] {
+            if on_segment(p, other)? {
+                shared.push((dot(difference(a.0, p), direction)?, p));
             }
-        } else if include_diagonal {
-            let mut x = x1 as isize;
-            let mut y = y1 as isize;
-            let steps = (x2 as isize - x1 as isize).abs();
-            let x_step = (x2 as isize - x1 as isize) / steps;
-            let y_step = (y2 as isize - y1 as isize) / steps;
-            for _ in 0..=steps {
-                grid[[y as usize, x as usize]] += 1;
-                x += x_step;
-                y += y_step;
+        }
+        shared.sort_unstable();
+        return Ok(match (shared.first(), shared.last()) {
+            (Some((_, start)), Some((_, end))) if start == end => {
+                Some(Intersection::Point(start.0 as f64, start.1 as f64))
             }
-        } else {
-            continue;
+            (Some((_, start)), Some((_, end))) => Some(Intersection::Overlap(*start, *end)),
+            _ => None,
+        });
+    }
+
+    // Solve a.0 + t * r = b.0 + u * s for t and u, which have to be within [0, 1]
+    let (mut t, mut u, mut denominator) = (cross(qp, s)?, cross(qp, r)?, denominator);
+    if denominator < 0 {
+        t = -t;
+        u = -u;
+        denominator = -denominator;
+    }
+    if t < 0 || t > denominator || u < 0 || u > denominator {
+        return Ok(None);
+    }
+    // The coordinates are exact up to the final division if the product fits
+    let offset = |r: i128| match t.checked_mul(r) {
+        Some(product) => product as f64 / denominator as f64,
+        None => t as f64 / denominator as f64 * r as f64,
+    };
+    Ok(Some(Intersection::Point(
+        a.0 .0 as f64 + offset(r.0),
+        a.0 .1 as f64 + offset(r.1),
+    )))
+}
+
+/// Determine for each pair of segments whether and where they intersect or overlap.
+///
+/// In contrast to the overlap counts, the segments are treated as continuous, so lines at
+/// arbitrary angles may cross in points with non-integer coordinates. Only pairs of segments
+/// that intersect are contained in the result. An error is returned for the first pair of
+/// segments whose coordinates are too far apart to be intersected exactly.
+pub fn intersections(lines: &[Line]) -> Result<Vec<SegmentIntersection>, IntersectionOverflow> {
+    let mut result = vec![];
+    for (first, second) in (0..lines.len()).tuple_combinations() {
+        let intersection = intersect(lines[first], lines[second])
+            .map_err(|_| IntersectionOverflow { first, second })?;
+        if let Some(intersection) = intersection {
+            result.push(SegmentIntersection {
+                first,
+                second,
+                intersection,
+            });
         }
     }
-    // check number of entries higher than 2
-    grid.iter().filter(|count| count > &&1).count()
+    Ok(result)
+}
+
+/// The grid is used as long as it has at most this many cells per covered point.
+const MAX_CELLS_PER_POINT: usize = 16;
+
+/// Number of points in the cartesian plane where more than one of the lines considered by
+/// the `rasterization` pass.
+///
+/// If the lines cover a sufficiently large fraction of the area they span and the area has at
+/// most `MAX_HEAT_MAP_CELLS` cells, the points are counted on a dense grid spanning their
+/// bounding box, otherwise in a hash map.
+pub fn count_overlaps(
+    data: &[String],
+    rasterization: Rasterization,
+) -> Result<usize, ParseLineError> {
+    // parse the input
+    let lines = parse_lines(data)?;
+    if lines.is_empty() {
+        return Ok(0);
+    }
+
+    let covered = lines
+        .iter()
+        .map(|line| lattice_steps(line).saturating_add(1))
+        .fold(0usize, usize::saturating_add);
+    let extent = |coordinates: Vec<isize>| {
+        let (min, max) = coordinates.into_iter().minmax().into_option().unwrap();
+        max.abs_diff(min).saturating_add(1)
+    };
+    let width = extent(
+        lines
+            .iter()
+            .flat_map(|((x1, _), (x2, _))| [*x1, *x2])
+            .collect(),
+    );
+    let height = extent(
+        lines
+            .iter()
+            .flat_map(|((_, y1), (_, y2))| [*y1, *y2])
+            .collect(),
+    );
+
+    if width.saturating_mul(height) <= covered.saturating_mul(MAX_CELLS_PER_POINT) {
+        if let Ok(count) = count_overlaps_dense(&lines, rasterization) {
+            return Ok(count);
+        }
+    }
+    Ok(count_overlaps_sparse(&lines, rasterization))
+}
+
+/// Number of points in the cartesian plane where more than one horizontal or vertical segments pass.
+/// If `include_diagonal=true` then also diagonals are included.
+pub fn day_5(data: &[String], include_diagonal: bool) -> usize {
+    let rasterization = if include_diagonal {
+        Rasterization::Diagonal
+    } else {
+        Rasterization::AxisAligned
+    };
+    count_overlaps(data, rasterization).expect("Invalid line in input!")
 }
 
 #[cfg(test)]
@@ -92,7 +468,122 @@ mod tests {
 
     #[test]
     fn test_get_coordinates() {
-        assert_eq!(get_coordinates(&"1,2 -> 3,4".to_string()), ((1, 2), (3, 4)));
+        assert_eq!(
+            get_coordinates(&"1,2 -> 3,4".to_string()),
+            Ok(((1, 2), (3, 4)))
+        );
+        assert_eq!(get_coordinates(" -1, +2->3 ,-4\t"), Ok(((-1, 2), (3, -4))));
+        assert_eq!(
+            get_coordinates(",2 -> 3,4"),
+            Err(ParseLineError::InvalidFormat(",2 -> 3,4".to_string()))
+        );
+        assert_eq!(
+            get_coordinates("1,2 -> 3"),
+            Err(ParseLineError::InvalidFormat("1,2 -> 3".to_string()))
+        );
+        assert_eq!(
+            get_coordinates("1,2 -> 3,99999999999999999999"),
+            Err(ParseLineError::InvalidNumber(
+                "99999999999999999999".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_negative_coordinates() {
+        let input = vec![
+            "-5,-3 -> -5,3".to_string(),
+            "-8,0 -> -2,0".to_string(),
+            "-8,-3 -> -2,3".to_string(),
+        ];
+        assert_eq!(day_5(&input, false), 1);
+        assert_eq!(day_5(&input, true), 1);
+
+        let lines = parse_lines(&input).unwrap();
+        let map = heat_map(&lines, Rasterization::Diagonal).unwrap();
+        assert_eq!(map.origin, (-8, -3));
+        assert_eq!(map.counts.dim(), (7, 7));
+        assert_eq!(map.count((-5, 0)), 3);
+        assert_eq!(map.count((-9, 0)), 0);
+        assert_eq!(
+            heat_map(
+                &[((isize::MIN, 0), (isize::MAX, 0))],
+                Rasterization::Diagonal
+            ),
+            Err(HeatMapTooLarge {
+                width: usize::MAX,
+                height: 1
+            })
+        );
+
+        let far = vec![
+            format!("{},0 -> {},0", isize::MIN, isize::MIN + 2),
+            format!("{},1 -> {},-1", isize::MIN, isize::MIN + 2),
+            format!(
+                "{},{} -> {},{}",
+                isize::MAX,
+                isize::MAX,
+                isize::MAX,
+                isize::MAX
+            ),
+            format!(
+                "{},{} -> {},{}",
+                isize::MAX,
+                isize::MAX,
+                isize::MAX,
+                isize::MAX
+            ),
+        ];
+        assert_eq!(count_overlaps(&far, Rasterization::Diagonal), Ok(2));
+    }
+
+    #[test]
+    fn test_full_range_intersections() {
+        let (min, max) = (isize::MIN, isize::MAX);
+        assert_eq!(
+            intersections(&[((min, min), (max, max)), ((min, max), (max, min))]),
+            Err(IntersectionOverflow {
+                first: 0,
+                second: 1
+            })
+        );
+
+        // Large coordinates are fine as long as the segments are not too long
+        let lines = [
+            ((max - 10, max - 10), (max, max)),
+            ((max - 1, max - 1), (max - 5, max - 5)),
+            ((max, max - 10), (max - 10, max)),
+            ((min, min), (min + (1 << 40), min + (1 << 40))),
+            ((min, min + (1 << 40)), (min + (1 << 40), min)),
+        ];
+        assert_eq!(
+            intersections(&lines),
+            Ok(vec![
+                SegmentIntersection {
+                    first: 0,
+                    second: 1,
+                    intersection: Intersection::Overlap((max - 5, max - 5), (max - 1, max - 1))
+                },
+                SegmentIntersection {
+                    first: 0,
+                    second: 2,
+                    intersection: Intersection::Point((max - 5) as f64, (max - 5) as f64)
+                },
+                SegmentIntersection {
+                    first: 1,
+                    second: 2,
+                    intersection: Intersection::Point((max - 5) as f64, (max - 5) as f64)
+                },
+                SegmentIntersection {
+                    first: 3,
+                    second: 4,
+                    intersection: Intersection::Point(
+                        (min + (1 << 39)) as f64,
+                        (min + (1 << 39)) as f64
+                    )
+                },
+            ])
+        );
     }
 
     #[test]
@@ -128,4 +619,134 @@ mod tests {
         ];
         assert_eq!(day_5(&input, true), 12);
     }
+
+    #[test]
+    fn test_sparse_and_dense() {
+        let input = vec![
+            "0,9 -> 5,9".to_string(),
+            "8,0 -> 0,8".to_string(),
+            "9,4 -> 3,4".to_string(),
+            "2,2 -> 2,1".to_string(),
+            "7,0 -> 7,4".to_string(),
+            "6,4 -> 2,0".to_string(),
+            "0,9 -> 2,9".to_string(),
+            "3,4 -> 1,4".to_string(),
+            "0,0 -> 8,8".to_string(),
+            "5,5 -> 8,2".to_string(),
+        ];
+        let lines = parse_lines(&input).unwrap();
+        for rasterization in [
+            Rasterization::AxisAligned,
+            Rasterization::Diagonal,
+            Rasterization::Arbitrary,
+        ] {
+            assert_eq!(
+                Ok(count_overlaps_sparse(&lines, rasterization)),
+                count_overlaps_dense(&lines, rasterization)
+            );
+        }
+    }
+
+    #[test]
+    fn test_huge_coordinates() {
+        let input = vec![
+            "1000000,3000000 -> 1000000,3000010".to_string(),
+            "999995,3000005 -> 1000005,3000005".to_string(),
+            "999990,2999990 -> 1000010,3000010".to_string(),
+        ];
+        assert_eq!(day_5(&input, false), 1);
+        assert_eq!(day_5(&input, true), 3);
+    }
+
+    #[test]
+    fn test_arbitrary_angles() {
+        assert_eq!(
+            points(&((1, 1), (7, 4)), Rasterization::Arbitrary).collect::<Vec<_>>(),
+            vec![(1, 1), (3, 2), (5, 3), (7, 4)]
+        );
+        assert_eq!(
+            points(&((7, 4), (1, 1)), Rasterization::Diagonal).count(),
+            0
+        );
+        assert_eq!(
+            points(&((2, 9), (2, 9)), Rasterization::AxisAligned).collect::<Vec<_>>(),
+            vec![(2, 9)]
+        );
+
+        let input = vec![
+            "1,1 -> 7,4".to_string(),
+            "3,0 -> 3,5".to_string(),
+            "0,4 -> 9,1".to_string(),
+            "5,3 -> 5,3".to_string(),
+        ];
+        // (3, 2) on the first two lines, (3, 3) on the second and the third, and (5, 3) on
+        // the first line and the single point
+        assert_eq!(count_overlaps(&input, Rasterization::Arbitrary), Ok(3));
+        assert_eq!(count_overlaps(&input, Rasterization::Diagonal), Ok(0));
+    }
+
+    #[test]
+    fn test_overlap_structure() {
+        let lines = vec![
+            ((0, 9), (5, 9)),
+            ((3, 9), (3, 7)),
+            ((8, 0), (0, 8)),
+            ((4, 9), (9, 9)),
+        ];
+
+        let map = heat_map(&lines, Rasterization::Diagonal).unwrap();
+        assert_eq!(map.origin, (0, 0));
+        assert_eq!(map.counts.dim(), (10, 10));
+        assert_eq!(map.count((3, 9)), 2);
+        assert_eq!(map.count((1, 7)), 1);
+        assert_eq!(map.count((11, 7)), 0);
+
+        assert_eq!(
+            overlap_points(&lines, Rasterization::Diagonal),
+            vec![((3, 9), 2), ((4, 9), 2), ((5, 9), 2)]
+        );
+
+        assert_eq!(
+            intersections(&lines),
+            Ok(vec![
+                SegmentIntersection {
+                    first: 0,
+                    second: 1,
+                    intersection: Intersection::Point(3.0, 9.0)
+                },
+                SegmentIntersection {
+                    first: 0,
+                    second: 3,
+                    intersection: Intersection::Overlap((4, 9), (5, 9))
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_intersect() {
+        // Crossing in a point with non-integer coordinates
+        assert_eq!(
+            intersect(((0, 0), (3, 1)), ((0, 1), (3, 0))),
+            Ok(Some(Intersection::Point(1.5, 0.5)))
+        );
+        // Parallel
+        assert_eq!(intersect(((0, 0), (3, 1)), ((0, 1), (3, 2))), Ok(None));
+        // Collinear but disjoint, touching, and contained
+        assert_eq!(intersect(((0, 0), (2, 2)), ((3, 3), (5, 5))), Ok(None));
+        assert_eq!(
+            intersect(((0, 0), (2, 2)), ((4, 4), (2, 2))),
+            Ok(Some(Intersection::Point(2.0, 2.0)))
+        );
+        assert_eq!(
+            intersect(((6, 0), (0, 6)), ((2, 4), (4, 2))),
+            Ok(Some(Intersection::Overlap((4, 2), (2, 4))))
+        );
+        // Single points
+        assert_eq!(
+            intersect(((2, 2), (2, 2)), ((0, 0), (4, 4))),
+            Ok(Some(Intersection::Point(2.0, 2.0)))
+        );
+        assert_eq!(intersect(((2, 3), (2, 3)), ((0, 0), (4, 4))), Ok(None));
+    }
 }
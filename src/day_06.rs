@@ -2,6 +2,295 @@
 //!
 //! This module contains the solution of the [sixth day's challenges](https://adventofcode.com/2021/day/6).
 
+/// The number of timer values a lanternfish can have.
+const TIMERS: usize = 9;
+
+/// A matrix mapping the animals per timer on one day to those on a later day.
+type Matrix = [[u128; TIMERS]; TIMERS];
+
+/// Count the animals per timer in the input.
+fn animals_per_timer(data: &[String]) -> [u128; TIMERS] {
+    data.iter()
+        .flat_map(|v| v.split(','))
+        .map(|c| c.parse::<usize>().unwrap())
+        .fold([0; TIMERS], |mut acc, i| {
+            acc[i] += 1;
+            acc
+        })
+}
+
+/// How the counts are combined when evolving the population.
+#[derive(Debug, Clone, Copy)]
+enum Arithmetic {
+    /// Counts larger than `u128::MAX` are clamped to `u128::MAX`.
+    Saturating,
+    /// Counts are reduced modulo the given modulus, which fits into a `u64`.
+    Modular(u128),
+}
+
+impl Arithmetic {
+    fn reduce(self, a: u128) -> u128 {
+        match self {
+            Arithmetic::Saturating => a,
+            Arithmetic::Modular(modulus) => a % modulus,
+        }
+    }
+
+    fn add(self, a: u128, b: u128) -> u128 {
+        match self {
+            Arithmetic::Saturating => a.saturating_add(b),
+            Arithmetic::Modular(modulus) => (a + b) % modulus,
+        }
+    }
+
+    fn mul(self, a: u128, b: u128) -> u128 {
+        match self {
+            Arithmetic::Saturating => a.saturating_mul(b),
+            Arithmetic::Modular(modulus) => a * b % modulus,
+        }
+    }
+}
+
+/// The matrix advancing the animals per timer by a single day.
+///
+/// Each timer is decreased by one, and the animals with timer 0 are both reset to 6 and
+/// spawn a new animal with timer 8.
+fn transition() -> Matrix {
+    let mut matrix = [[0; TIMERS]; TIMERS];
+    for timer in 1..TIMERS {
+        matrix[timer - 1][timer] = 1;
+    }
+    matrix[6][0] = 1;
+    matrix[8][0] = 1;
+    matrix
+}
+
+fn multiply(a: &Matrix, b: &Matrix, arithmetic: Arithmetic) -> Matrix {
+    let mut product = [[0; TIMERS]; TIMERS];
+    for (i, row) in product.iter_mut().enumerate() {
+        for (j, entry) in row.iter_mut().enumerate() {
+            *entry = (0..TIMERS).fold(0, |acc, k| {
+                arithmetic.add(acc, arithmetic.mul(a[i][k], b[k][j]))
+            });
+        }
+    }
+    product
+}
+
+fn apply(matrix: &Matrix, counts: &[u128; TIMERS], arithmetic: Arithmetic) -> [u128; TIMERS] {
+    let mut result = [0; TIMERS];
+    for (entry, row) in result.iter_mut().zip(matrix) {
+        *entry = row
+            .iter()
+            .zip(counts)
+            .fold(0, |acc, (m, c)| arithmetic.add(acc, arithmetic.mul(*m, *c)));
+    }
+    result
+}
+
+/// Advance the animals per timer by `days` by applying the `days`-th power of the transition
+/// matrix, computed by repeated squaring in `O(log(days))` matrix multiplications.
+fn evolve(counts: [u128; TIMERS], mut days: u64, arithmetic: Arithmetic) -> [u128; TIMERS] {
+    let mut counts = counts.map(|count| arithmetic.reduce(count));
+    let mut power = transition();
+    while days > 0 {
+        if days & 1 == 1 {
+            counts = apply(&power, &counts, arithmetic);
+        }
+        days >>= 1;
+        if days > 0 {
+            power = multiply(&power, &power, arithmetic);
+        }
+    }
+    counts
+}
+
+/// Compute the number of lanternfish after a given number of `days`, or `None` if it does
+/// not fit below `u128::MAX`.
+///
+/// Since all counts are non-negative, saturating arithmetic yields the exact counts as long
+/// as they stay below `u128::MAX`, even if intermediate powers of the transition matrix
+/// saturate.
+pub fn population(data: &[String], days: u64) -> Option<u128> {
+    let arithmetic = Arithmetic::Saturating;
+    let total = evolve(animals_per_timer(data), days, arithmetic)
+        .into_iter()
+        .fold(0, |acc, count| arithmetic.add(acc, count));
+    if total == u128::MAX {
+        None
+    } else {
+        Some(total)
+    }
+}
+
+/// Compute the number of lanternfish after a given number of `days` modulo `modulus`.
+///
+/// # Panics
+///
+/// Panics if `modulus` is zero.
+pub fn population_modulo(data: &[String], days: u64, modulus: u64) -> u64 {
+    assert!(modulus > 0, "Expected a positive modulus!");
+    let arithmetic = Arithmetic::Modular(modulus as u128);
+    evolve(animals_per_timer(data), days, arithmetic)
+        .into_iter()
+        .fold(0, |acc, count| arithmetic.add(acc, count)) as u64
+}
+
+/// The rules by which a population of fish evolves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lifecycle {
+    /// The timer of a newly spawned fish.
+    pub newborn_timer: usize,
+    /// The timer of a fish after it has spawned.
+    pub reset_timer: usize,
+    /// The number of fish spawned each time a timer passes zero.
+    pub offspring: u128,
+    /// The number of times a fish spawns before it dies, if it dies at all.
+    pub max_cycles: Option<usize>,
+}
+
+impl Lifecycle {
+    /// The life cycle of the lanternfish in the puzzle.
+    pub const LANTERNFISH: Lifecycle = Lifecycle {
+        newborn_timer: 8,
+        reset_timer: 6,
+        offspring: 1,
+        max_cycles: None,
+    };
+}
+
+/// The state of a population on a given day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The number of days since the start.
+    pub day: usize,
+    /// The number of fish per timer value.
+    pub buckets: Vec<u128>,
+    /// The total number of fish.
+    pub total: u128,
+}
+
+/// A population of fish evolving according to a [`Lifecycle`].
+///
+/// Iterating over the population yields its snapshots, starting with the initial state on
+/// day 0. The iteration ends as soon as a count no longer fits into a `u128`.
+#[derive(Debug, Clone)]
+pub struct Population {
+    lifecycle: Lifecycle,
+    day: usize,
+    /// The number of fish per completed cycles and timer, or `None` after an overflow.
+    counts: Option<Vec<Vec<u128>>>,
+}
+
+impl Population {
+    /// Create a population from the timers of the initial fish, none of which has spawned yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `lifecycle` lets fish die before they have spawned even once.
+    pub fn new(lifecycle: Lifecycle, timers: &[usize]) -> Self {
+        assert!(
+            lifecycle.max_cycles != Some(0),
+            "Expected fish to spawn at least once!"
+        );
+        let width = timers
+            .iter()
+            .chain([&lifecycle.newborn_timer, &lifecycle.reset_timer])
+            .max()
+            .unwrap()
+            + 1;
+        let mut counts = vec![vec![0; width]; lifecycle.max_cycles.unwrap_or(1)];
+        for timer in timers {
+            counts[0][*timer] += 1;
+        }
+        Population {
+            lifecycle,
+            day: 0,
+            counts: Some(counts),
+        }
+    }
+
+    /// Create a population from the comma-separated timers in the input.
+    pub fn from_input(lifecycle: Lifecycle, data: &[String]) -> Self {
+        let timers = data
+            .iter()
+            .flat_map(|v| v.split(','))
+            .map(|c| c.parse::<usize>().unwrap())
+            .collect::<Vec<_>>();
+        Population::new(lifecycle, &timers)
+    }
+
+    /// The counts on the next day, or `None` if they overflow.
+    fn advance(&self, counts: &[Vec<u128>]) -> Option<Vec<Vec<u128>>> {
+        let Lifecycle {
+            newborn_timer,
+            reset_timer,
+            offspring,
+            max_cycles,
+        } = self.lifecycle;
+        let mut next = vec![vec![0u128; counts[0].len()]; counts.len()];
+        let mut newborns = 0u128;
+        for (cycle, row) in counts.iter().enumerate() {
+            for timer in 1..row.len() {
+                next[cycle][timer - 1] = next[cycle][timer - 1].checked_add(row[timer])?;
+            }
+            // Fish that have spawned for the last time die
+            let spawning = row[0];
+            newborns = newborns.checked_add(spawning.checked_mul(offspring)?)?;
+            let survivors = match max_cycles {
+                None => Some(cycle),
+                Some(max_cycles) => Some(cycle + 1).filter(|c| *c < max_cycles),
+            };
+            if let Some(survivors) = survivors {
+                next[survivors][reset_timer] =
+                    next[survivors][reset_timer].checked_add(spawning)?;
+            }
+        }
+        next[0][newborn_timer] = next[0][newborn_timer].checked_add(newborns)?;
+        Some(next)
+    }
+
+    /// The snapshot of the counts, or `None` if they overflow.
+    fn snapshot(&self, counts: &[Vec<u128>]) -> Option<Snapshot> {
+        let mut buckets = vec![0u128; counts[0].len()];
+        for row in counts {
+            for (bucket, count) in buckets.iter_mut().zip(row) {
+                *bucket = bucket.checked_add(*count)?;
+            }
+        }
+        let total = buckets
+            .iter()
+            .try_fold(0u128, |acc, count| acc.checked_add(*count))?;
+        Some(Snapshot {
+            day: self.day,
+            buckets,
+            total,
+        })
+    }
+}
+
+impl Iterator for Population {
+    type Item = Snapshot;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let counts = self.counts.take()?;
+        let snapshot = self.snapshot(&counts)?;
+        self.counts = self.advance(&counts);
+        self.day += 1;
+        Some(snapshot)
+    }
+}
+
+/// The total number of fish on each day from day 0 up to and including `days`.
+///
+/// The timeline is cut short if the population no longer fits into a `u128`.
+pub fn timeline(lifecycle: Lifecycle, data: &[String], days: usize) -> Vec<u128> {
+    Population::from_input(lifecycle, data)
+        .take(days + 1)
+        .map(|snapshot| snapshot.total)
+        .collect()
+}
+
 /// Compute the number of lanternfish after a given number of `days`.
 ///
 /// To have an algorithm constant in time and memory, we only keep track of the number of animals per timer.
@@ -13,14 +302,7 @@
 /// Hence the number of animals for timer 6 on day `n+1` corresponds to the number of animals for timer 7 on day `n`, plus the
 /// number of animals on timer 0 on day `n`.
 pub fn day_6(data: &[String], days: usize) -> usize {
-    let mut animals_per_timer: Vec<usize> = data
-        .iter()
-        .flat_map(|v| v.split(','))
-        .map(|c| c.parse::<usize>().unwrap())
-        .fold(vec![0; 9], |mut acc, i| {
-            acc[i] += 1;
-            acc
-        });
+    let mut animals_per_timer = animals_per_timer(data);
 
     for _ in 0..days {
         let zero = animals_per_timer[0];
@@ -31,7 +313,7 @@ pub fn day_6(data: &[String], days: usize) -> usize {
         animals_per_timer[6] += zero;
     }
 
-    animals_per_timer.iter().sum()
+    usize::try_from(animals_per_timer.iter().sum::<u128>()).expect("Too many lanternfish!")
 }
 
 #[cfg(test)]
@@ -50,4 +332,130 @@ mod tests {
         let input = vec!["3,4,3,1,2".to_string()];
         assert_eq!(day_6(&input, 256), 26984457539);
     }
+
+    #[test]
+    fn test_population() {
+        let input = vec!["3,4,3,1,2".to_string()];
+        for days in 0..=256 {
+            assert_eq!(
+                population(&input, days),
+                Some(day_6(&input, days as usize) as u128)
+            );
+        }
+        assert_eq!(population(&input, 256), Some(26984457539));
+        assert_eq!(population(&input, 1_000_000_000_000), None);
+    }
+
+    #[test]
+    fn test_population_overflow() {
+        // Simulate day by day with checked arithmetic. A single fish with timer 8 lags behind
+        // the fish with timer 0 in the powers of the transition matrix.
+        for input in [vec!["8".to_string()], vec!["0,1,2,3,4,5,6,7,8".to_string()]] {
+            let mut counts = animals_per_timer(&input).map(Some);
+            for days in 0..1200 {
+                let total = counts
+                    .iter()
+                    .try_fold(0u128, |acc, count| count.and_then(|c| acc.checked_add(c)))
+                    .filter(|total| *total != u128::MAX);
+                assert_eq!(population(&input, days), total);
+                counts.rotate_left(1);
+                counts[6] = counts[6].zip(counts[8]).and_then(|(a, b)| a.checked_add(b));
+            }
+            assert_eq!(population(&input, 1199), None);
+        }
+    }
+
+    #[test]
+    fn test_population_modulo() {
+        let input = vec!["3,4,3,1,2".to_string()];
+        assert_eq!(population_modulo(&input, 256, 1_000_000_007), 984457357);
+        assert_eq!(population_modulo(&input, 0, 3), 2);
+        assert_eq!(population_modulo(&input, 256, 1), 0);
+        let total = population(&input, 900).unwrap();
+        for modulus in [2, 1_000_000_007, u64::MAX] {
+            assert_eq!(
+                population_modulo(&input, 900, modulus) as u128,
+                total % modulus as u128
+            );
+        }
+        // Astronomically many days finish instantly
+        population_modulo(&input, 1_000_000_000_000, 1_000_000_007);
+        population_modulo(&input, u64::MAX, u64::MAX);
+    }
+
+    #[test]
+    fn test_lanternfish_lifecycle() {
+        let input = vec!["3,4,3,1,2".to_string()];
+        let totals = timeline(Lifecycle::LANTERNFISH, &input, 256);
+        assert_eq!(totals.len(), 257);
+        for (days, total) in totals.into_iter().enumerate() {
+            assert_eq!(total, day_6(&input, days) as u128);
+        }
+
+        let mut fish = Population::from_input(Lifecycle::LANTERNFISH, &input);
+        assert_eq!(
+            fish.nth(18),
+            Some(Snapshot {
+                day: 18,
+                buckets: vec![3, 5, 3, 2, 2, 1, 5, 1, 4],
+                total: 26,
+            })
+        );
+
+        // The population ends as soon as it overflows
+        let days = Population::from_input(Lifecycle::LANTERNFISH, &input).count();
+        assert!(population(&input, days as u64 - 1).is_some());
+        assert!(population(&input, days as u64).is_none());
+    }
+
+    #[test]
+    fn test_custom_lifecycle() {
+        let lifecycle = Lifecycle {
+            newborn_timer: 2,
+            reset_timer: 1,
+            offspring: 1,
+            max_cycles: Some(2),
+        };
+        let mut fish = Population::new(lifecycle, &[0]);
+        let totals = fish
+            .by_ref()
+            .take(4)
+            .map(|snapshot| snapshot.total)
+            .collect::<Vec<_>>();
+        assert_eq!(totals, vec![1, 2, 2, 2]);
+        assert_eq!(
+            fish.next(),
+            Some(Snapshot {
+                day: 4,
+                buckets: vec![0, 2, 1],
+                total: 3,
+            })
+        );
+
+        let lifecycle = Lifecycle {
+            newborn_timer: 0,
+            reset_timer: 0,
+            offspring: 2,
+            max_cycles: None,
+        };
+        assert_eq!(
+            timeline(lifecycle, &["0".to_string()], 5),
+            vec![1, 3, 9, 27, 81, 243]
+        );
+
+        // Fish spawning once and dying afterwards keep the population constant
+        let lifecycle = Lifecycle {
+            max_cycles: Some(1),
+            ..Lifecycle::LANTERNFISH
+        };
+        assert_eq!(timeline(lifecycle, &input_of(&[8, 20]), 100), vec![2; 101]);
+    }
+
+    fn input_of(timers: &[usize]) -> Vec<String> {
+        vec![timers
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>()
+            .join(",")]
+    }
 }
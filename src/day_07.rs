@@ -2,24 +2,148 @@
 //!
 //! This module contains the solution of the [seventh day's challenges](https://adventofcode.com/2021/day/7).
 
-/// The sum of the integers from 1 to `x`.
-fn gauss(x: isize) -> isize {
-    x * (x + 1) / 2
+/// The position at which the crabs align and the fuel they need to get there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alignment {
+    pub position: isize,
+    pub fuel: i128,
 }
 
-/// Compute the minimal amount of fuel required to align the crabs at one horizontal position.
-///
-/// Moving a crab one horizontal unit costs one fuel. Solution by trying all.
-pub fn day_7_1(data: &[String]) -> isize {
-    let input: Vec<isize> = data[0]
+/// Parse the horizontal positions of the crabs.
+pub fn parse_positions(data: &[String]) -> Vec<isize> {
+    data[0]
         .split(',')
         .map(|c| c.parse::<isize>().unwrap())
-        .collect();
+        .collect()
+}
+
+/// The fuel a crab needs to move a given distance.
+///
+/// The alignment can only be found efficiently by [`align`] if the cost of each crab is
+/// non-decreasing and convex in the distance.
+pub trait FuelCost {
+    /// The fuel the crab with index `crab` needs to move `distance` units.
+    fn cost(&self, crab: usize, distance: i128) -> i128;
+}
+
+/// Moving one unit costs one fuel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Linear;
+
+impl FuelCost for Linear {
+    fn cost(&self, _crab: usize, distance: i128) -> i128 {
+        distance
+    }
+}
+
+/// The `n`-th unit moved costs `n` fuel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Triangular;
+
+impl FuelCost for Triangular {
+    fn cost(&self, _crab: usize, distance: i128) -> i128 {
+        distance * (distance + 1) / 2
+    }
+}
+
+/// Moving a distance costs its square in fuel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quadratic;
+
+impl FuelCost for Quadratic {
+    fn cost(&self, _crab: usize, distance: i128) -> i128 {
+        distance * distance
+    }
+}
+
+/// Each crab's cost is scaled by its weight, e.g. for crabs with differently efficient engines.
+///
+/// The weights are indexed like the crabs and must not be negative.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Weighted<C> {
+    pub weights: Vec<i128>,
+    pub cost: C,
+}
+
+impl<C: FuelCost> FuelCost for Weighted<C> {
+    fn cost(&self, crab: usize, distance: i128) -> i128 {
+        self.weights[crab] * self.cost.cost(crab, distance)
+    }
+}
+
+/// The fuel needed to move all crabs to `position`.
+pub fn total_fuel(positions: &[isize], position: isize, cost: &impl FuelCost) -> i128 {
+    positions
+        .iter()
+        .enumerate()
+        .map(|(crab, x)| cost.cost(crab, (*x as i128 - position as i128).abs()))
+        .sum()
+}
+
+/// Align the crabs for an arbitrary convex cost, or `None` if there are no crabs.
+///
+/// Since the costs do not decrease with the distance, the optimum lies between the outermost
+/// crabs. As the total fuel is convex in the position, the smallest optimal position is the
+/// first one from which the fuel no longer decreases, which is found by a binary search in
+/// `O(n log(max - min))`.
+pub fn align(positions: &[isize], cost: &impl FuelCost) -> Option<Alignment> {
+    let (mut low, mut high) = (*positions.iter().min()?, *positions.iter().max()?);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if total_fuel(positions, middle + 1, cost) >= total_fuel(positions, middle, cost) {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    Some(Alignment {
+        position: low,
+        fuel: total_fuel(positions, low, cost),
+    })
+}
+
+/// Align the crabs if moving one unit costs one fuel, or `None` if there are no crabs.
+///
+/// The sum of distances is minimal at the median. Of several optimal positions, the smallest
+/// one is chosen.
+pub fn align_linear(positions: &[isize]) -> Option<Alignment> {
+    if positions.is_empty() {
+        return None;
+    }
+    let mut selected = positions.to_vec();
+    let position = *selected.select_nth_unstable((positions.len() - 1) / 2).1;
+    Some(Alignment {
+        position,
+        fuel: total_fuel(positions, position, &Linear),
+    })
+}
 
-    (0..*input.iter().max().unwrap())
-        .map(|pos| input.iter().map(|x| (x - pos).abs()).sum::<isize>())
-        .min()
-        .unwrap()
+/// Align the crabs if the `n`-th unit moved costs `n` fuel, or `None` if there are no crabs.
+///
+/// The cost `d * (d + 1) / 2` of moving a distance `d` is `d^2 / 2` plus half the linear
+/// cost. The derivative of the total cost therefore vanishes within half a unit of the mean,
+/// and the optimal integer position is one of the integers next to that point. Of several
+/// optimal positions, the smallest one is chosen.
+pub fn align_triangular(positions: &[isize]) -> Option<Alignment> {
+    if positions.is_empty() {
+        return None;
+    }
+    let sum = positions.iter().map(|x| *x as i128).sum::<i128>();
+    let mean = sum.div_euclid(positions.len() as i128) as isize;
+    (mean.saturating_sub(1)..=mean.saturating_add(2))
+        .map(|position| Alignment {
+            position,
+            fuel: total_fuel(positions, position, &Triangular),
+        })
+        .min_by_key(|alignment| (alignment.fuel, alignment.position))
+}
+
+/// Compute the minimal amount of fuel required to align the crabs at one horizontal position.
+///
+/// Moving a crab one horizontal unit costs one fuel.
+pub fn day_7_1(data: &[String]) -> isize {
+    let alignment = align_linear(&parse_positions(data)).expect("No crabs!");
+    isize::try_from(alignment.fuel).expect("Fuel overflows!")
 }
 
 /// Compute the minimal amount of fuel required to align the crabs at one horizontal position.
@@ -27,21 +151,15 @@ pub fn day_7_1(data: &[String]) -> isize {
 /// Moving a crab costs increasingly more fuel. The first move costs one fuel, t
 /// the second move costs two fuel e.t.c.
 pub fn day_7_2(data: &[String]) -> isize {
-    let input: Vec<isize> = data[0]
-        .split(',')
-        .map(|c| c.parse::<isize>().unwrap())
-        .collect();
-
-    (0..*input.iter().max().unwrap())
-        .map(|pos| input.iter().map(|x| gauss((x - pos).abs())).sum::<isize>())
-        .min()
-        .unwrap()
+    let alignment = align_triangular(&parse_positions(data)).expect("No crabs!");
+    isize::try_from(alignment.fuel).expect("Fuel overflows!")
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::Lcg;
 
     #[test]
     fn test_day_7_1() {
@@ -54,4 +172,132 @@ mod tests {
         let input = vec!["16,1,2,0,4,2,7,1,2,14".to_string()];
         assert_eq!(day_7_2(&input), 168);
     }
+
+    /// Find the optimal alignment by trying all positions between the outermost crabs.
+    fn brute_force(positions: &[isize], cost: &impl FuelCost) -> Alignment {
+        (*positions.iter().min().unwrap()..=*positions.iter().max().unwrap())
+            .map(|position| Alignment {
+                position,
+                fuel: total_fuel(positions, position, cost),
+            })
+            .min_by_key(|alignment| (alignment.fuel, alignment.position))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_alignment() {
+        let positions = parse_positions(&["16,1,2,0,4,2,7,1,2,14".to_string()]);
+        assert_eq!(
+            align_linear(&positions),
+            Some(Alignment {
+                position: 2,
+                fuel: 37
+            })
+        );
+        assert_eq!(
+            align_triangular(&positions),
+            Some(Alignment {
+                position: 5,
+                fuel: 168
+            })
+        );
+        assert_eq!(align_linear(&[]), None);
+        assert_eq!(align_triangular(&[]), None);
+        // The rightmost crab is a valid position
+        assert_eq!(day_7_1(&["5".to_string()]), 0);
+        assert_eq!(day_7_2(&["3,9".to_string()]), 12);
+    }
+
+    #[test]
+    fn test_alignment_brute_force() {
+        let mut random = Lcg::new(7);
+        for _ in 0..300 {
+            let len = 1 + random.next_below(12);
+            let range = 1 + random.next_below(50) as isize;
+            let positions = (0..len)
+                .map(|_| random.next_below(range as usize) as isize - range / 2)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                align_linear(&positions),
+                Some(brute_force(&positions, &Linear))
+            );
+            assert_eq!(
+                align_triangular(&positions),
+                Some(brute_force(&positions, &Triangular))
+            );
+        }
+    }
+
+    #[test]
+    fn test_many_crabs() {
+        let mut random = Lcg::new(42);
+        let positions = (0..1_000_000)
+            .map(|_| random.next_below(1 << 31) as isize * 1000)
+            .collect::<Vec<_>>();
+        let linear = align_linear(&positions).unwrap();
+        let triangular = align_triangular(&positions).unwrap();
+        for delta in [-1, 1] {
+            assert!(total_fuel(&positions, linear.position + delta, &Linear) >= linear.fuel);
+            assert!(
+                total_fuel(&positions, triangular.position + delta, &Triangular) >= triangular.fuel
+            );
+        }
+    }
+
+    #[test]
+    fn test_convex_costs() {
+        let positions = parse_positions(&["16,1,2,0,4,2,7,1,2,14".to_string()]);
+        assert_eq!(align(&positions, &Linear), align_linear(&positions));
+        assert_eq!(align(&positions, &Triangular), align_triangular(&positions));
+        // The squared distances are minimal at the mean, 4.9
+        assert_eq!(
+            align(&positions, &Quadratic),
+            Some(Alignment {
+                position: 5,
+                fuel: 291
+            })
+        );
+        // A heavy crab pulls the others towards it
+        let mut weights = vec![1; positions.len()];
+        weights[0] = 10;
+        assert_eq!(
+            align(
+                &positions,
+                &Weighted {
+                    weights,
+                    cost: Linear
+                }
+            ),
+            Some(Alignment {
+                position: 16,
+                fuel: 111
+            })
+        );
+        assert_eq!(align(&[], &Quadratic), None);
+
+        let mut random = Lcg::new(3);
+        for _ in 0..300 {
+            let len = 1 + random.next_below(12);
+            let positions = (0..len)
+                .map(|_| random.next_below(40) as isize - 20)
+                .collect::<Vec<_>>();
+            let weighted = Weighted {
+                weights: (0..len).map(|_| random.next_below(5) as i128).collect(),
+                cost: Triangular,
+            };
+            for (actual, expected) in [
+                (align(&positions, &Linear), brute_force(&positions, &Linear)),
+                (
+                    align(&positions, &Quadratic),
+                    brute_force(&positions, &Quadratic),
+                ),
+                (
+                    align(&positions, &weighted),
+                    brute_force(&positions, &weighted),
+                ),
+            ] {
+                assert_eq!(actual, Some(expected));
+            }
+        }
+    }
 }
@@ -1,26 +1,12 @@
 //! # Advent of Code 2021 - Day 8
 //!
 //! This module contains the solution of the [eigth day's challenges](https://adventofcode.com/2021/day/8).
-use std::collections::{HashMap, HashSet};
+use crate::Lcg;
+use itertools::Itertools;
+use std::fmt;
 
-/// Count the number of times the digits `1`, `4`, `7` or `8` appear in the output.
-///
-/// They can be easily spotted as they have a unique number of segments, namely 2 for `1`,
-/// 4 for `4`, 3 for `7` and 7 for `8`.
-pub fn day_8_1(data: &[String]) -> usize {
-    data.iter()
-        .map(|s| s.split('|').last().unwrap().trim())
-        .map(|out| {
-            out.split_whitespace()
-                .filter(|number| matches!(number.chars().count(), 2 | 3 | 4 | 7))
-                .count()
-        })
-        .sum()
-}
-
-/// Determine the sum of all the numbers represented in the output.
-///
-/// To do so, we start by adding an index to each segment. This is done according to
+/// The segments lit for each digit, where segment `i` is represented by bit `i - 1` and the
+/// segments are numbered according to
 ///
 /// ```sh
 /// 11111
@@ -31,132 +17,351 @@ pub fn day_8_1(data: &[String]) -> usize {
 /// 5   3
 /// 44444
 /// ```
+const DIGIT_MASKS: [u8; 10] = [
+    0b0111111, // 1, 2, 3, 4, 5, 6
+    0b0000110, // 2, 3
+    0b1011011, // 1, 2, 4, 5, 7
+    0b1001111, // 1, 2, 3, 4, 7
+    0b1100110, // 2, 3, 6, 7
+    0b1101101, // 1, 3, 4, 6, 7
+    0b1111101, // 1, 3, 4, 5, 6, 7
+    0b0000111, // 1, 2, 3
+    0b1111111, // 1, 2, 3, 4, 5, 6, 7
+    0b1101111, // 1, 2, 3, 4, 6, 7
+];
+
+/// The bitmask of all seven segments or wires.
+const ALL: u8 = 0b1111111;
+
+/// The wires of the display, connected to the segments in some unknown order.
+const WIRES: &str = "abcdefg";
+
+/// The reasons why a display cannot be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The line does not contain a `|` separating the patterns from the output.
+    MissingSeparator(String),
+    /// A pattern contains a character that is not one of the wires `a` to `g`.
+    InvalidWire(char),
+    /// A pattern contains the same wire more than once.
+    RepeatedWire(String),
+    /// No wiring lets all patterns show digits.
+    Inconsistent,
+    /// Several wirings let all patterns show digits.
+    Ambiguous { wirings: usize },
+    /// The segments of a wiring are not a permutation of the segments 1 to 7.
+    InvalidWiring { segments: [usize; 7] },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::MissingSeparator(s) => write!(f, "missing separator in {:?}", s),
+            DecodeError::InvalidWire(c) => write!(f, "invalid wire {:?}", c),
+            DecodeError::RepeatedWire(s) => write!(f, "repeated wire in pattern {:?}", s),
+            DecodeError::Inconsistent => write!(f, "no wiring matches the patterns"),
+            DecodeError::Ambiguous { wirings } => {
+                write!(f, "{} wirings match the patterns", wirings)
+            }
+            DecodeError::InvalidWiring { segments } => {
+                write!(f, "segments {:?} are not a permutation of 1 to 7", segments)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// The connection of the wires to the segments of a display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Wiring {
+    /// The segment driven by each of the wires `a` to `g`.
+    segments: [usize; 7],
+}
+
+impl Wiring {
+    /// Connect the wires `a` to `g` to the `segments`, which have to be a permutation of the
+    /// segments 1 to 7.
+    pub fn new(segments: [usize; 7]) -> Result<Self, DecodeError> {
+        let mask = segments
+            .iter()
+            .filter(|segment| (1..=7).contains(*segment))
+            .fold(0u8, |acc, segment| acc | 1 << (segment - 1));
+        if mask == ALL {
+            Ok(Wiring { segments })
+        } else {
+            Err(DecodeError::InvalidWiring { segments })
+        }
+    }
+
+    /// The segment driven by each of the wires `a` to `g`.
+    pub fn segments(&self) -> [usize; 7] {
+        self.segments
+    }
+
+    /// The segment driven by the `wire`, if it is one of `a` to `g`.
+    pub fn segment(&self, wire: char) -> Option<usize> {
+        WIRES.find(wire).map(|index| self.segments[index])
+    }
+
+    /// The pattern of wires showing the `digit`, in alphabetical order.
+    pub fn pattern(&self, digit: usize) -> String {
+        WIRES
+            .chars()
+            .zip(self.segments)
+            .filter(|(_, segment)| DIGIT_MASKS[digit] & 1 << (*segment - 1) != 0)
+            .map(|(wire, _)| wire)
+            .collect()
+    }
+
+    /// The digit shown when the wires in the bitmask are active, if any.
+    fn digit(&self, wires: u8) -> Option<usize> {
+        let segments = self
+            .segments
+            .iter()
+            .enumerate()
+            .filter(|(wire, _)| wires & (1 << wire) != 0)
+            .fold(0u8, |acc, (_, segment)| acc | 1 << (segment - 1));
+        DIGIT_MASKS.iter().position(|digit| *digit == segments)
+    }
+}
+
+/// A decoded display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    /// The only wiring under which all patterns show digits.
+    pub wiring: Wiring,
+    /// The digits of the output.
+    pub digits: Vec<usize>,
+    /// The number formed by the digits of the output.
+    pub value: usize,
+}
+
+/// The segments each wire may be connected to, as bitmasks.
 ///
-/// Then the algorithm proceeds in 6 steps.
+/// A pattern with `n` wires shows one of the digits with `n` segments. Its wires are
+/// therefore connected to segments lit by one of these digits, and the segments lit by all of
+/// them are driven by its wires, e.g. the wires of the pattern for 1 drive the segments 2
+/// and 3, while all other wires drive other segments.
+fn candidates(signals: &[u8]) -> [u8; 7] {
+    let mut candidates = [ALL; 7];
+    for &wires in signals {
+        let digits = DIGIT_MASKS
+            .iter()
+            .filter(|digit| digit.count_ones() == wires.count_ones());
+        let any = digits.clone().fold(0, |acc, digit| acc | digit);
+        let all = digits.fold(ALL, |acc, digit| acc & digit);
+        for (wire, candidates) in candidates.iter_mut().enumerate() {
+            if wires & 1 << wire != 0 {
+                *candidates &= any;
+            } else {
+                *candidates &= !all;
+            }
+        }
+    }
+    candidates
+}
+
+/// Collect all wirings under which every signal shows a digit.
 ///
-/// 1. Identify the signals corresponding to the numbers 1, 4, 7 and 8.
-///    This is easy as they have a unique number of segments.
-/// 2. The difference of the signals for numbers 1 and 7 gives the letter associated
-///    to segment 1.
-/// 3. Take the signal corresponding to number 8 and compare it to the 3 numbers with
-///    6 segmets.
-/// 4. Find the one differing char that appears in the signal of 8 but not 1. This
-///    gives segment 2.
-/// 5. Segment 3 is given by the other char appearing the signal of number 1.
-/// 6. Find the differing char that appear in the signal of number 4.
-///    This gives segment 7.
-/// 7. The remaining char in the signal of number 4 gives segment 6.
-/// 8. The last char in the differences with the signal of eight gives segement 5,
-/// 9. The only letter that has not been assigned belongs to segment 4.
-pub fn identify_output(data: Vec<HashSet<char>>, out: Vec<String>) -> usize {
-    // Create a hashmap mapping each letter to a segment index
-    let mut map: HashMap<char, Option<usize>> = "abcdefg".chars().map(|c| (c, None)).collect();
-    // Create a map that maps specific signals to integers
-    let digit_map: HashMap<Vec<usize>, usize> = HashMap::from([
-        (vec![1, 2, 3, 4, 5, 6], 0),
-        (vec![2, 3], 1),
-        (vec![1, 2, 4, 5, 7], 2),
-        (vec![1, 2, 3, 4, 7], 3),
-        (vec![2, 3, 6, 7], 4),
-        (vec![1, 3, 4, 6, 7], 5),
-        (vec![1, 3, 4, 5, 6, 7], 6),
-        (vec![1, 2, 3], 7),
-        (vec![1, 2, 3, 4, 5, 6, 7], 8),
-        (vec![1, 2, 3, 4, 6, 7], 9),
-    ]);
-
-    // Identify the HashSets corresponding to the numbers 1, 4, 7, 8
-    let one = data.iter().find(|&h| h.len() == 2).unwrap();
-    let four = data.iter().find(|&h| h.len() == 4).unwrap();
-    let seven = data.iter().find(|&h| h.len() == 3).unwrap();
-    let eight = data.iter().find(|&h| h.len() == 7).unwrap();
-
-    // Keep only the elements of four that do not appear in one
-    let four: HashSet<_> = four.difference(one).collect();
-
-    let segment_1_letter = seven.difference(one).into_iter().next().unwrap();
-    *map.get_mut(segment_1_letter).unwrap() = Some(1);
-
-    // Get the 3 6-segment numbers
-    let six_segment_numbers: Vec<_> = data.iter().filter(|h| h.len() == 6).collect();
-    let six_segment_diff_8: Vec<_> = six_segment_numbers
-        .iter()
-        .map(|&six| eight.difference(six).into_iter().next().unwrap())
-        .collect();
+/// The wires from `wire` onwards are connected to the remaining candidate segments, which
+/// are not `used` by the previous wires.
+fn search(
+    wire: usize,
+    used: u8,
+    segments: &mut [usize; 7],
+    candidates: &[u8; 7],
+    signals: &[u8],
+    wirings: &mut Vec<Wiring>,
+) {
+    if wire == segments.len() {
+        let wiring = Wiring {
+            segments: *segments,
+        };
+        if signals.iter().all(|wires| wiring.digit(*wires).is_some()) {
+            wirings.push(wiring);
+        }
+        return;
+    }
+    for segment in 1..=7 {
+        let bit = 1 << (segment - 1);
+        if candidates[wire] & bit != 0 && used & bit == 0 {
+            segments[wire] = segment;
+            search(wire + 1, used | bit, segments, candidates, signals, wirings);
+        }
+    }
+}
 
-    // Find the segment 2 letter
-    let segment_2_letter = six_segment_diff_8
-        .iter()
-        .find(|&&c| one.contains(c))
-        .unwrap();
-    *map.get_mut(segment_2_letter).unwrap() = Some(2);
+/// The bitmask of the wires in the pattern, where wire `a` is represented by bit 0.
+fn wire_mask(pattern: &str) -> Result<u8, DecodeError> {
+    pattern.chars().try_fold(0u8, |acc, wire| {
+        let bit = 1 << WIRES.find(wire).ok_or(DecodeError::InvalidWire(wire))?;
+        if acc & bit != 0 {
+            Err(DecodeError::RepeatedWire(pattern.to_string()))
+        } else {
+            Ok(acc | bit)
+        }
+    })
+}
 
-    let segment_3_letter = one.iter().find(|&c| &c != segment_2_letter).unwrap();
-    *map.get_mut(segment_3_letter).unwrap() = Some(3);
+/// Count the number of times the digits `1`, `4`, `7` or `8` appear in the output.
+///
+/// They can be easily spotted as they have a unique number of segments, namely 2 for `1`,
+/// 4 for `4`, 3 for `7` and 7 for `8`.
+pub fn day_8_1(data: &[String]) -> usize {
+    data.iter()
+        .map(|s| s.rsplit('|').next().unwrap().trim())
+        .map(|out| {
+            out.split_whitespace()
+                .filter(|number| matches!(number.chars().count(), 2 | 3 | 4 | 7))
+                .count()
+        })
+        .sum()
+}
 
-    // Find the segment 4 letter
-    let segment_7_letter = six_segment_diff_8
+/// Determine the number represented in the output.
+///
+/// The wiring is recovered as the only one of the 5040 ways to connect the wires to the
+/// segments under which every signal pattern and every output pattern shows a digit. The
+/// signal patterns therefore need not contain all ten digits, as long as they and the output
+/// determine the wiring. The search only tries segments that are compatible with the lengths
+/// of the patterns containing a wire, which leaves a single wiring for complete inputs.
+pub fn identify_output(patterns: &[String], out: &[String]) -> Result<Decoded, DecodeError> {
+    let signals = patterns
         .iter()
-        .find(|&&c| four.contains(c))
-        .unwrap();
-    *map.get_mut(segment_7_letter).unwrap() = Some(7);
+        .chain(out)
+        .map(|pattern| wire_mask(pattern))
+        .collect::<Result<Vec<_>, _>>()?;
 
-    // Other remaining char in signal of four gives segment 6
-    let segment_6_letter = four.into_iter().find(|&c| &c != segment_7_letter).unwrap();
-    *map.get_mut(segment_6_letter).unwrap() = Some(6);
+    let mut wirings = vec![];
+    search(
+        0,
+        0,
+        &mut [0; 7],
+        &candidates(&signals),
+        &signals,
+        &mut wirings,
+    );
+    let wiring = match wirings.as_slice() {
+        [] => return Err(DecodeError::Inconsistent),
+        [wiring] => *wiring,
+        _ => {
+            return Err(DecodeError::Ambiguous {
+                wirings: wirings.len(),
+            })
+        }
+    };
 
-    // Remaining diff gives segment 5
-    let segment_5_letter = six_segment_diff_8
+    let digits = signals[patterns.len()..]
         .iter()
-        .find(|&&c| &c != segment_2_letter && &c != segment_7_letter)
-        .unwrap();
-    *map.get_mut(segment_5_letter).unwrap() = Some(5);
+        .map(|wires| wiring.digit(*wires).unwrap())
+        .collect::<Vec<_>>();
+    let value = digits.iter().fold(0, |acc, digit| acc * 10 + digit);
+    Ok(Decoded {
+        wiring,
+        digits,
+        value,
+    })
+}
 
-    // Remainng letter is segment 4
-    let key = map
-        .iter()
-        .find_map(|(k, v)| if v.is_none() { Some(*k) } else { None })
-        .unwrap();
-    *map.get_mut(&key).unwrap() = Some(4);
-
-    out.into_iter()
-        .map(|o| {
-            o.chars()
-                .map(|letter| map[&letter].unwrap())
-                .collect::<Vec<_>>()
-        })
-        .map(|mut v| {
-            v.sort_unstable();
-            v
-        })
-        .map(|vec| digit_map[&vec])
-        .rev()
-        .enumerate()
-        .fold(0, |acc, (i, x)| acc + x * 10usize.pow(i as u32))
+/// Decode a line of the input of the form `patterns | output`.
+pub fn decode_line(line: &str) -> Result<Decoded, DecodeError> {
+    let (patterns, out) = line
+        .split_once('|')
+        .ok_or_else(|| DecodeError::MissingSeparator(line.to_string()))?;
+    let words = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+    identify_output(&words(patterns), &words(out))
 }
 
 /// Compute the sum of the output numbers
 pub fn day_8_2(data: &[String]) -> usize {
     data.iter()
-        .map(|s| s.split('|'))
-        .map(|mut i| {
-            let input = i
-                .next()
-                .unwrap()
-                .split_whitespace()
-                .map(|s| s.trim().chars().collect())
-                .collect();
-            let output = i
-                .next()
-                .unwrap()
-                .split_whitespace()
-                .map(|s| s.to_string())
-                .collect();
-            identify_output(input, output)
-        })
+        .map(|s| decode_line(s).expect("Invalid display!").value)
         .sum()
 }
 
+/// The minimal number of digits in the output of a display.
+const OUTPUT_DIGITS: usize = 4;
+
+/// Encode the `number` as a line of the input, showing the ten digits in ascending order
+/// followed by the digits of the `number`, padded with zeros to at least four digits.
+pub fn encode(number: usize, wiring: &Wiring) -> String {
+    let patterns = (0..DIGIT_MASKS.len())
+        .map(|digit| wiring.pattern(digit))
+        .join(" ");
+    let out = format!("{:0width$}", number, width = OUTPUT_DIGITS)
+        .chars()
+        .map(|digit| wiring.pattern(digit.to_digit(10).unwrap() as usize))
+        .join(" ");
+    format!("{} | {}", patterns, out)
+}
+
+/// A line of the input along with the wiring and number it encodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub line: String,
+    pub wiring: Wiring,
+    pub value: usize,
+}
+
+/// An endless source of random, valid lines of the input.
+///
+/// The wiring, the four-digit number, the order of the patterns and the order of the wires
+/// within each pattern are chosen by a linear congruential generator, so the same `seed`
+/// always yields the same samples.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    random: Lcg,
+}
+
+impl Generator {
+    /// Create a generator whose sequence of samples is determined by the `seed`.
+    pub fn new(seed: u64) -> Self {
+        Generator {
+            random: Lcg::new(seed),
+        }
+    }
+
+    /// Shuffle the `items` with the Fisher-Yates algorithm.
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.random.next_below(i + 1);
+            items.swap(i, j);
+        }
+    }
+
+    /// The `pattern` with its wires in random order.
+    fn scramble(&mut self, pattern: &str) -> String {
+        let mut wires = pattern.chars().collect::<Vec<_>>();
+        self.shuffle(&mut wires);
+        wires.into_iter().collect()
+    }
+}
+
+impl Iterator for Generator {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut segments = [1, 2, 3, 4, 5, 6, 7];
+        self.shuffle(&mut segments);
+        let wiring = Wiring::new(segments).unwrap();
+        let value = self.random.next_below(10usize.pow(OUTPUT_DIGITS as u32));
+
+        let line = encode(value, &wiring);
+        let (patterns, out) = line.split_once(" | ").unwrap();
+        let mut patterns = patterns.split(' ').collect::<Vec<_>>();
+        self.shuffle(&mut patterns);
+        let patterns = patterns.into_iter().map(|p| self.scramble(p)).join(" ");
+        let out = out.split(' ').map(|p| self.scramble(p)).join(" ");
+
+        Some(Sample {
+            line: format!("{} | {}", patterns, out),
+            wiring,
+            value,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -182,27 +387,47 @@ mod tests {
 
     #[test]
     fn test_identify_output() {
-        let data: Vec<HashSet<_>> = vec![
-            "acedgfb".chars().collect(),
-            "cdfbe".chars().collect(),
-            "gcdfa".chars().collect(),
-            "fbcad".chars().collect(),
-            "dab".chars().collect(),
-            "cefabd".chars().collect(),
-            "cdfgeb".chars().collect(),
-            "eafb".chars().collect(),
-            "cagedb".chars().collect(),
-            "ab".chars().collect(),
-        ];
+        let words = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+        let data = words("acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab");
+        let out = words("cdfeb fcadb cdfeb cdbaf");
 
-        let out = vec![
-            "cdfeb".to_string(),
-            "fcadb".to_string(),
-            "cdfeb".to_string(),
-            "cdbaf".to_string(),
-        ];
+        let decoded = identify_output(&data, &out).unwrap();
+        assert_eq!(decoded.value, 5353);
+        assert_eq!(decoded.digits, vec![5, 3, 5, 3]);
+        assert_eq!(decoded.wiring, Wiring::new([2, 3, 4, 1, 6, 7, 5]).unwrap());
+        assert_eq!(decoded.wiring.segment('d'), Some(1));
+        assert_eq!(decoded.wiring.segment('x'), None);
+
+        // A subset of the patterns may still determine the wiring
+        let partial = words("acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb");
+        assert_eq!(identify_output(&partial, &out).unwrap().value, 5353);
+    }
 
-        assert_eq!(identify_output(data, out), 5353);
+    #[test]
+    fn test_decode_errors() {
+        let words = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            decode_line("ab dab"),
+            Err(DecodeError::MissingSeparator("ab dab".to_string()))
+        );
+        assert_eq!(
+            identify_output(&words("ab dax"), &[]),
+            Err(DecodeError::InvalidWire('x'))
+        );
+        assert_eq!(
+            identify_output(&words("aab"), &[]),
+            Err(DecodeError::RepeatedWire("aab".to_string()))
+        );
+        // No digit has 8 segments, and no two digits with two segments exist
+        assert_eq!(
+            identify_output(&words("ab cd"), &[]),
+            Err(DecodeError::Inconsistent)
+        );
+        // 1 and 7 only determine the top segment, leaving 2 * 4! wirings
+        assert_eq!(
+            identify_output(&words("ab dab"), &words("ab")),
+            Err(DecodeError::Ambiguous { wirings: 48 })
+        );
     }
 
     #[test]
@@ -222,4 +447,51 @@ mod tests {
 
         assert_eq!(day_8_2(&input), 61229);
     }
+
+    #[test]
+    fn test_encode() {
+        let wiring = Wiring::new([2, 3, 4, 1, 6, 7, 5]).unwrap();
+        let line = encode(5353, &wiring);
+        assert_eq!(
+            line,
+            "abcdeg ab acdfg abcdf abef bcdef bcdefg abd abcdefg abcdef | bcdef abcdf bcdef abcdf"
+        );
+        assert_eq!(decode_line(&line).unwrap().wiring, wiring);
+        assert_eq!(
+            decode_line(&encode(42, &wiring)).unwrap().digits,
+            vec![0, 0, 4, 2]
+        );
+        assert_eq!(decode_line(&encode(123456, &wiring)).unwrap().value, 123456);
+
+        assert_eq!(
+            Wiring::new([0, 3, 4, 1, 6, 7, 5]),
+            Err(DecodeError::InvalidWiring {
+                segments: [0, 3, 4, 1, 6, 7, 5]
+            })
+        );
+        assert_eq!(
+            Wiring::new([2, 2, 4, 1, 6, 7, 5]),
+            Err(DecodeError::InvalidWiring {
+                segments: [2, 2, 4, 1, 6, 7, 5]
+            })
+        );
+    }
+
+    #[test]
+    fn test_generated_displays() {
+        let samples = Generator::new(2021).take(500).collect::<Vec<_>>();
+        for sample in &samples {
+            let decoded = decode_line(&sample.line).unwrap();
+            assert_eq!(decoded.wiring, sample.wiring);
+            assert_eq!(decoded.value, sample.value);
+        }
+        let data = samples.iter().map(|s| s.line.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            day_8_2(&data),
+            samples.iter().map(|s| s.value).sum::<usize>()
+        );
+        // The generator is deterministic but varies the wirings
+        assert_eq!(Generator::new(2021).next(), samples.first().cloned());
+        assert!(samples.iter().map(|s| s.wiring).unique().count() > 300);
+    }
 }
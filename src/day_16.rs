@@ -3,210 +3,1287 @@
 //! This module contains the solution of the [sixteenth day's challenges](https://adventofcode.com/2021/day/16).
 use itertools::Itertools;
 use std::borrow::Borrow;
+use std::fmt;
+use std::io::{self, prelude::*, BufReader};
 
 /// Packets can be either of type `Literal` or `Operator`, where operators contain sub-packages.
 #[derive(Debug, PartialEq)]
-enum PacketType {
-    Literal(usize),
+pub enum PacketType {
+    Literal(u128),
     Operator(Vec<Packet>),
 }
 
 /// A package has a `version`, a `type_id` and a `package_type`.
 #[derive(Debug, PartialEq)]
-struct Packet {
-    version: usize,
-    type_id: usize,
-    package_type: PacketType,
+pub struct Packet {
+    pub version: usize,
+    pub type_id: usize,
+    pub package_type: PacketType,
 }
 
+/// The errors that can occur while decoding a transmission.
+///
+/// Each variant carries the bit `offset` into the binary representation of the
+/// transmission at which the problem was detected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input contains a character that is not a hexadecimal digit.
+    InvalidHexDigit { offset: usize, digit: char },
+    /// The transmission ended while `needed` more bits were expected.
+    Truncated { offset: usize, needed: usize },
+    /// The sub-packets of a length-type 0 operator extend past the declared length.
+    SubPacketOverrun { offset: usize, end: usize },
+    /// The literal value does not fit into the integer type used for packet values.
+    LiteralOverflow { offset: usize },
+    /// The operator does not have the number of operands its type id requires.
+    InvalidOperandCount {
+        offset: usize,
+        type_id: usize,
+        count: usize,
+    },
+    /// The packet is nested more than `MAX_DEPTH` levels deep.
+    TooDeep { offset: usize },
+}
+
+/// The maximum nesting depth of packets, the outermost packet has depth 0.
+///
+/// Deeper transmissions are rejected instead of exhausting the stack while decoding.
+pub const MAX_DEPTH: usize = 256;
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidHexDigit { offset, digit } => {
+                write!(f, "invalid hex digit {:?} at bit {}", digit, offset)
+            }
+            DecodeError::Truncated { offset, needed } => write!(
+                f,
+                "transmission truncated at bit {}, {} more bits expected",
+                offset, needed
+            ),
+            DecodeError::SubPacketOverrun { offset, end } => write!(
+                f,
+                "sub-packets of the operator at bit {} extend past bit {}",
+                offset, end
+            ),
+            DecodeError::LiteralOverflow { offset } => {
+                write!(f, "literal at bit {} overflows", offset)
+            }
+            DecodeError::InvalidOperandCount {
+                offset,
+                type_id,
+                count,
+            } => write!(
+                f,
+                "operator with type id {} at bit {} has {} operands",
+                type_id, offset, count
+            ),
+            DecodeError::TooDeep { offset } => write!(
+                f,
+                "packet at bit {} is nested more than {} levels deep",
+                offset, MAX_DEPTH
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// The errors that can occur while evaluating a packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvaluateError {
+    /// The value of the packet with the given type id does not fit into the integer type
+    /// used for the evaluation.
+    Overflow { type_id: usize },
+    /// The operator does not have the number of operands its type id requires.
+    InvalidOperandCount { type_id: usize, count: usize },
+}
+
+impl fmt::Display for EvaluateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvaluateError::Overflow { type_id } => {
+                write!(f, "value of packet with type id {} overflows", type_id)
+            }
+            EvaluateError::InvalidOperandCount { type_id, count } => write!(
+                f,
+                "operator with type id {} cannot have {} operands",
+                type_id, count
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EvaluateError {}
+
+/// Unsigned integer types in which packets can be evaluated.
+///
+/// Decoded literals never exceed a `u128`, but implementing this trait for a wider type
+/// allows evaluating sums and products whose results do not fit into a `u128`.
+pub trait PacketValue: Copy + Ord {
+    const ZERO: Self;
+    const ONE: Self;
+
+    /// Convert a literal, returning `None` if it does not fit.
+    fn from_literal(literal: u128) -> Option<Self>;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+}
+
+macro_rules! impl_packet_value {
+    ($($t:ty),*) => {
+        $(
+            impl PacketValue for $t {
+                const ZERO: Self = 0;
+                const ONE: Self = 1;
+
+                fn from_literal(literal: u128) -> Option<Self> {
+                    Self::try_from(literal).ok()
+                }
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$t>::checked_add(self, other)
+                }
+
+                fn checked_mul(self, other: Self) -> Option<Self> {
+                    <$t>::checked_mul(self, other)
+                }
+            }
+        )*
+    };
+}
+
+impl_packet_value!(u64, u128, usize);
+
 impl Packet {
-    fn value(&self) -> usize {
+    /// Compute the value of the packet.
+    ///
+    /// Panics if the packet is malformed or if the value or any intermediate result does
+    /// not fit into a `usize`. Use `evaluate` to handle these errors or to evaluate in a
+    /// wider integer type.
+    pub fn value(&self) -> usize {
+        self.evaluate().expect("Invalid packet!")
+    }
+
+    /// Compute the value of the packet in the integer type `T`.
+    ///
+    /// An error is returned if an operator does not have the number of operands its type id
+    /// requires, which can only happen for packets that were not decoded, or if a literal or
+    /// an intermediate result does not fit into `T`.
+    pub fn evaluate<T: PacketValue>(&self) -> Result<T, EvaluateError> {
+        let overflow = EvaluateError::Overflow {
+            type_id: self.type_id,
+        };
         match &self.package_type {
-            PacketType::Literal(val) => *val,
-            PacketType::Operator(packets) => match self.type_id {
-                0 => packets.iter().map(|p| p.value()).sum(),
-                1 => packets.iter().map(|p| p.value()).product(),
-                2 => packets.iter().map(|p| p.value()).min().unwrap(),
-                3 => packets.iter().map(|p| p.value()).max().unwrap(),
-                5 => {
-                    if packets[0].value() > packets[1].value() {
-                        1
-                    } else {
-                        0
+            PacketType::Literal(val) => T::from_literal(*val).ok_or(overflow),
+            PacketType::Operator(packets) => {
+                check_operands(0, self.type_id, packets.len()).map_err(|_| {
+                    EvaluateError::InvalidOperandCount {
+                        type_id: self.type_id,
+                        count: packets.len(),
                     }
-                }
-                6 => {
-                    if packets[0].value() < packets[1].value() {
-                        1
+                })?;
+                let values = packets
+                    .iter()
+                    .map(|p| p.evaluate())
+                    .collect::<Result<Vec<T>, _>>()?;
+                match self.type_id {
+                    0 => values
+                        .into_iter()
+                        .try_fold(T::ZERO, T::checked_add)
+                        .ok_or(overflow),
+                    1 => values
+                        .into_iter()
+                        .try_fold(T::ONE, T::checked_mul)
+                        .ok_or(overflow),
+                    2 => Ok(values.into_iter().min().unwrap()),
+                    3 => Ok(values.into_iter().max().unwrap()),
+                    5 => Ok(if values[0] > values[1] {
+                        T::ONE
                     } else {
-                        0
-                    }
-                }
-                _ => {
-                    if packets[0].value() == packets[1].value() {
-                        1
+                        T::ZERO
+                    }),
+                    6 => Ok(if values[0] < values[1] {
+                        T::ONE
                     } else {
-                        0
-                    }
+                        T::ZERO
+                    }),
+                    _ => Ok(if values[0] == values[1] {
+                        T::ONE
+                    } else {
+                        T::ZERO
+                    }),
                 }
-            },
+            }
         }
     }
 }
 
 /// Convert the hexadecimal input format to binary.
-fn convert_hex_to_binary(hex: &str) -> Vec<char> {
-    hex.chars()
-        .flat_map(|c| match c {
-            '0' => ['0', '0', '0', '0'],
-            '1' => ['0', '0', '0', '1'],
-            '2' => ['0', '0', '1', '0'],
-            '3' => ['0', '0', '1', '1'],
-            '4' => ['0', '1', '0', '0'],
-            '5' => ['0', '1', '0', '1'],
-            '6' => ['0', '1', '1', '0'],
-            '7' => ['0', '1', '1', '1'],
-            '8' => ['1', '0', '0', '0'],
-            '9' => ['1', '0', '0', '1'],
-            'A' => ['1', '0', '1', '0'],
-            'B' => ['1', '0', '1', '1'],
-            'C' => ['1', '1', '0', '0'],
-            'D' => ['1', '1', '0', '1'],
-            'E' => ['1', '1', '1', '0'],
-            _ => ['1', '1', '1', '1'],
+///
+/// Both upper- and lowercase digits are accepted.
+fn convert_hex_to_binary(hex: &str) -> Result<Vec<char>, DecodeError> {
+    let mut binary = Vec::with_capacity(4 * hex.len());
+    for (i, c) in hex.chars().enumerate() {
+        let digit = c.to_digit(16).ok_or(DecodeError::InvalidHexDigit {
+            offset: 4 * i,
+            digit: c,
+        })?;
+        (0..4)
+            .rev()
+            .for_each(|b| binary.push(if digit >> b & 1 == 1 { '1' } else { '0' }));
+    }
+    Ok(binary)
+}
+
+/// Read `len` bits starting at `start` as an unsigned number.
+fn read_bits(binary: &[char], start: usize, len: usize) -> Result<usize, DecodeError> {
+    let bits = binary
+        .get(start..start + len)
+        .ok_or_else(|| DecodeError::Truncated {
+            offset: binary.len(),
+            needed: start + len - binary.len(),
+        })?;
+    Ok(bits
+        .iter()
+        .fold(0, |acc, &b| acc << 1 | if b == '1' { 1 } else { 0 }))
+}
+
+/// Read the version and the type id of the packet starting at `start_index`.
+fn parse_header(start_index: usize, binary: &[char]) -> Result<(usize, usize), DecodeError> {
+    let version = read_bits(binary, start_index, 3)?;
+    let type_id = read_bits(binary, start_index + 3, 3)?;
+    Ok((version, type_id))
+}
+
+/// Make sure an operator has as many operands as its type id requires.
+///
+/// Sum, product, minimum and maximum need at least one operand, comparisons exactly two.
+/// Type id 4 denotes literals and cannot be used for operators.
+fn check_operands(start_index: usize, type_id: usize, count: usize) -> Result<(), DecodeError> {
+    let valid = match type_id {
+        0..=3 => count >= 1,
+        5..=7 => count == 2,
+        _ => false,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(DecodeError::InvalidOperandCount {
+            offset: start_index,
+            type_id,
+            count,
         })
-        .collect()
+    }
 }
 
 /// Parse a literal type packet.
-fn parse_literal(start_index: usize, binary: &[char]) -> (Packet, usize) {
-    let version =
-        usize::from_str_radix(&binary[start_index..start_index + 3].iter().join(""), 2).unwrap();
-    let type_id =
-        usize::from_str_radix(&binary[start_index + 3..start_index + 6].iter().join(""), 2)
-            .unwrap();
+fn parse_literal(start_index: usize, binary: &[char]) -> Result<(Packet, usize), DecodeError> {
+    let (version, type_id) = parse_header(start_index, binary)?;
 
     let mut index = start_index + 6;
-    let mut number = vec![];
+    let mut number: u128 = 0;
 
     loop {
-        if binary[index] == '1' {
-            (index + 1..=index + 4).for_each(|i| number.push(binary[i]));
-            index += 5;
-        } else {
-            (index + 1..=index + 4).for_each(|i| number.push(binary[i]));
-            index += 5;
+        let group = read_bits(binary, index, 5)?;
+        number = number
+            .checked_mul(16)
+            .map(|n| n | group as u128 & 0b1111)
+            .ok_or(DecodeError::LiteralOverflow {
+                offset: start_index,
+            })?;
+        index += 5;
+        if group & 0b10000 == 0 {
             break;
         }
     }
 
-    let number = usize::from_str_radix(&number.iter().join(""), 2).unwrap();
-
-    (
+    Ok((
         Packet {
             version,
             type_id,
             package_type: PacketType::Literal(number),
         },
         index,
-    )
+    ))
 }
 
-/// Parse an operator packet with length-type id 0.
-fn parse_operator_0(start_index: usize, binary: &[char]) -> (Packet, usize) {
-    let version =
-        usize::from_str_radix(&binary[start_index..start_index + 3].iter().join(""), 2).unwrap();
-    let type_id =
-        usize::from_str_radix(&binary[start_index + 3..start_index + 6].iter().join(""), 2)
-            .unwrap();
+/// Parse an operator packet with length-type id 0 at nesting level `depth`.
+fn parse_operator_0(
+    start_index: usize,
+    depth: usize,
+    binary: &[char],
+) -> Result<(Packet, usize), DecodeError> {
+    let (version, type_id) = parse_header(start_index, binary)?;
 
     debug_assert_eq!(binary[start_index + 6], '0');
 
     // Next 15 bits indicate the total length of subpackets
-    let n_bits = usize::from_str_radix(
-        &binary[start_index + 7..start_index + 22].iter().join(""),
-        2,
-    )
-    .unwrap();
+    let n_bits = read_bits(binary, start_index + 7, 15)?;
 
     let next_package_start_index = start_index + 22 + n_bits;
 
     let mut index = start_index + 22;
     let mut sub_packets = vec![];
 
-    loop {
-        let (packet, next_index) = parse_packet(index, binary);
+    while index < next_package_start_index {
+        let (packet, next_index) = parse_packet(index, depth + 1, binary)?;
+        if next_index > next_package_start_index {
+            return Err(DecodeError::SubPacketOverrun {
+                offset: start_index,
+                end: next_package_start_index,
+            });
+        }
         sub_packets.push(packet);
         index = next_index;
-        if next_index == next_package_start_index {
-            break;
-        }
     }
 
-    (
+    check_operands(start_index, type_id, sub_packets.len())?;
+
+    Ok((
         Packet {
             version,
             type_id,
             package_type: PacketType::Operator(sub_packets),
         },
         next_package_start_index,
-    )
+    ))
 }
 
-/// Parse an operator packet with length-type id 1.
-fn parse_operator_1(start_index: usize, binary: &[char]) -> (Packet, usize) {
-    let version =
-        usize::from_str_radix(&binary[start_index..start_index + 3].iter().join(""), 2).unwrap();
-    let type_id =
-        usize::from_str_radix(&binary[start_index + 3..start_index + 6].iter().join(""), 2)
-            .unwrap();
+/// Parse an operator packet with length-type id 1 at nesting level `depth`.
+fn parse_operator_1(
+    start_index: usize,
+    depth: usize,
+    binary: &[char],
+) -> Result<(Packet, usize), DecodeError> {
+    let (version, type_id) = parse_header(start_index, binary)?;
 
     debug_assert_eq!(binary[start_index + 6], '1');
 
     // Next 11 bits indicate the total number of subpackets
-    let n_packets = usize::from_str_radix(
-        &binary[start_index + 7..start_index + 18].iter().join(""),
-        2,
-    )
-    .unwrap();
+    let n_packets = read_bits(binary, start_index + 7, 11)?;
+
+    check_operands(start_index, type_id, n_packets)?;
 
     let mut index = start_index + 18;
     let mut sub_packets = vec![];
 
-    loop {
-        let (packet, next_index) = parse_packet(index, binary);
+    for _ in 0..n_packets {
+        let (packet, next_index) = parse_packet(index, depth + 1, binary)?;
         sub_packets.push(packet);
         index = next_index;
-        if sub_packets.len() == n_packets {
-            break;
-        }
     }
 
-    (
+    Ok((
         Packet {
             version,
             type_id,
             package_type: PacketType::Operator(sub_packets),
         },
         index,
-    )
+    ))
 }
 
-/// Parse a general operator type packet.
-fn parse_operator(start_index: usize, binary: &[char]) -> (Packet, usize) {
+/// Parse a general operator type packet at nesting level `depth`.
+fn parse_operator(
+    start_index: usize,
+    depth: usize,
+    binary: &[char],
+) -> Result<(Packet, usize), DecodeError> {
     // Check the length type id
-    match binary[start_index + 6] {
-        '0' => parse_operator_0(start_index, binary),
-        _ => parse_operator_1(start_index, binary),
+    match read_bits(binary, start_index + 6, 1)? {
+        0 => parse_operator_0(start_index, depth, binary),
+        _ => parse_operator_1(start_index, depth, binary),
+    }
+}
+
+/// Parse a generic packet at nesting level `depth`.
+fn parse_packet(
+    start_index: usize,
+    depth: usize,
+    binary: &[char],
+) -> Result<(Packet, usize), DecodeError> {
+    if depth > MAX_DEPTH {
+        return Err(DecodeError::TooDeep {
+            offset: start_index,
+        });
+    }
+    match parse_header(start_index, binary)? {
+        (_, 4) => parse_literal(start_index, binary),
+        _ => parse_operator(start_index, depth, binary),
+    }
+}
+
+/// Decode the outermost packet of a hexadecimal transmission.
+///
+/// Any bits following the outermost packet are considered padding and ignored.
+pub fn decode(hex: &str) -> Result<Packet, DecodeError> {
+    let binary = convert_hex_to_binary(hex)?;
+    parse_packet(0, 0, &binary).map(|(packet, _)| packet)
+}
+
+impl DecodeError {
+    /// Move the bit offset of the error by `by` bits.
+    fn shifted(self, by: usize) -> Self {
+        match self {
+            DecodeError::InvalidHexDigit { offset, digit } => DecodeError::InvalidHexDigit {
+                offset: offset + by,
+                digit,
+            },
+            DecodeError::Truncated { offset, needed } => DecodeError::Truncated {
+                offset: offset + by,
+                needed,
+            },
+            DecodeError::SubPacketOverrun { offset, end } => DecodeError::SubPacketOverrun {
+                offset: offset + by,
+                end: end + by,
+            },
+            DecodeError::LiteralOverflow { offset } => DecodeError::LiteralOverflow {
+                offset: offset + by,
+            },
+            DecodeError::InvalidOperandCount {
+                offset,
+                type_id,
+                count,
+            } => DecodeError::InvalidOperandCount {
+                offset: offset + by,
+                type_id,
+                count,
+            },
+            DecodeError::TooDeep { offset } => DecodeError::TooDeep {
+                offset: offset + by,
+            },
+        }
+    }
+}
+
+/// The errors that can occur while decoding a stream of transmissions.
+#[derive(Debug)]
+pub enum StreamError {
+    /// Reading from the underlying source failed.
+    Io(io::Error),
+    /// The transmission could not be decoded. Bit offsets are counted from the start of
+    /// the stream, not counting whitespace.
+    Decode(DecodeError),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Io(e) => write!(f, "failed to read transmission: {}", e),
+            StreamError::Decode(e) => write!(f, "failed to decode transmission: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+impl From<io::Error> for StreamError {
+    fn from(e: io::Error) -> Self {
+        StreamError::Io(e)
+    }
+}
+
+impl From<DecodeError> for StreamError {
+    fn from(e: DecodeError) -> Self {
+        StreamError::Decode(e)
+    }
+}
+
+/// The number of hex digits read from the source when more bits are needed.
+///
+/// The number doubles for every unsuccessful attempt to decode a packet, so that the work
+/// spent on retries stays proportional to the size of the packet.
+const INITIAL_CHUNK_SIZE: usize = 16;
+
+/// An iterator over the top-level packets of hexadecimal transmissions read from a source.
+///
+/// Transmissions may be concatenated or separated by whitespace such as line breaks. After
+/// a packet, the bits up to the next hex digit are skipped. Zero bits at the end of a
+/// whitespace-separated transmission are considered padding, so transmissions whose padding
+/// spans additional hex digits must be separated by whitespace. Whitespace within a packet
+/// is skipped, so long transmissions may be wrapped over several lines.
+///
+/// The iterator ends after the first error.
+pub struct PacketStream<R: Read> {
+    bytes: io::Bytes<BufReader<R>>,
+    /// The bits of the current transmission that have not been decoded yet.
+    buffer: Vec<char>,
+    /// The number of bits that have been read from the source.
+    position: usize,
+    /// The number of bits that have been removed from the front of the buffer.
+    consumed: usize,
+    /// Whether whitespace or the end of the source has been reached by the last read.
+    segment_ended: bool,
+    /// Whether the end of the source has been reached.
+    eof: bool,
+    /// Whether an error occurred.
+    failed: bool,
+    chunk_size: usize,
+}
+
+impl<R: Read> PacketStream<R> {
+    /// Create a stream decoding the transmissions read from `reader`.
+    pub fn new(reader: R) -> Self {
+        Self {
+            bytes: BufReader::new(reader).bytes(),
+            buffer: vec![],
+            position: 0,
+            consumed: 0,
+            segment_ended: false,
+            eof: false,
+            failed: false,
+            chunk_size: INITIAL_CHUNK_SIZE,
+        }
+    }
+
+    /// Read up to `chunk_size` hex digits of the current transmission into the buffer.
+    fn fill(&mut self) -> Result<(), StreamError> {
+        for _ in 0..self.chunk_size {
+            let c = match self.bytes.next() {
+                Some(byte) => byte? as char,
+                None => {
+                    self.eof = true;
+                    self.segment_ended = true;
+                    break;
+                }
+            };
+            if c.is_ascii_whitespace() {
+                self.segment_ended = true;
+                break;
+            }
+            self.buffer.extend(
+                convert_hex_to_binary(&c.to_string()).map_err(|e| e.shifted(self.position))?,
+            );
+            self.position += 4;
+        }
+        Ok(())
+    }
+
+    /// Remove the first `n` bits from the buffer.
+    fn consume(&mut self, n: usize) {
+        self.buffer.drain(..n);
+        self.consumed += n;
+    }
+
+    /// Decode the next packet.
+    fn next_packet(&mut self) -> Result<Option<Packet>, StreamError> {
+        loop {
+            if !self.buffer.contains(&'1') {
+                // Nothing but padding in the current transmission
+                if self.segment_ended {
+                    let n = self.buffer.len();
+                    self.consume(n);
+                    if self.eof {
+                        return Ok(None);
+                    }
+                    self.segment_ended = false;
+                }
+                self.fill()?;
+                continue;
+            }
+
+            match parse_packet(0, 0, &self.buffer) {
+                Ok((packet, end)) => {
+                    self.consume(end.div_ceil(4) * 4);
+                    self.chunk_size = INITIAL_CHUNK_SIZE;
+                    return Ok(Some(packet));
+                }
+                Err(DecodeError::Truncated { .. }) if !self.eof => {
+                    // Whitespace inside a packet does not end the transmission
+                    self.segment_ended = false;
+                    self.fill()?;
+                    self.chunk_size *= 2;
+                }
+                Err(e) => return Err(e.shifted(self.consumed).into()),
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for PacketStream<R> {
+    type Item = Result<Packet, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.next_packet().transpose();
+        self.failed = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+/// The short name of the operation associated to a type id.
+fn mnemonic(type_id: usize) -> &'static str {
+    match type_id {
+        0 => "sum",
+        1 => "prod",
+        2 => "min",
+        3 => "max",
+        4 => "lit",
+        5 => "gt",
+        6 => "lt",
+        _ => "eq",
+    }
+}
+
+/// The raw fields following the header of a packet in a disassembly listing.
+#[derive(Debug, PartialEq)]
+pub enum Fields {
+    /// The 4-bit groups of a literal and their value, if it fits into a `u128`.
+    Literal {
+        groups: Vec<usize>,
+        value: Option<u128>,
+    },
+    /// The length type id of an operator and either its number of sub-packet bits
+    /// (length type 0) or its number of sub-packets (length type 1).
+    Operator {
+        length_type_id: usize,
+        length: usize,
+    },
+}
+
+/// A single line of a disassembly listing, describing one packet.
+#[derive(Debug, PartialEq)]
+pub struct Instruction {
+    /// The bit offset of the packet in the transmission.
+    pub offset: usize,
+    /// The nesting depth of the packet, the outermost packet has depth 0.
+    pub depth: usize,
+    pub version: usize,
+    pub type_id: usize,
+    pub fields: Fields,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>6}:  {:03b} {:03b}  {}{:<4} v{}",
+            self.offset,
+            self.version,
+            self.type_id,
+            "  ".repeat(self.depth),
+            mnemonic(self.type_id),
+            self.version
+        )?;
+        match &self.fields {
+            Fields::Literal { groups, value } => {
+                let groups = groups.iter().map(|g| format!("{:04b}", g)).join(" ");
+                match value {
+                    Some(value) => write!(f, " [{}] = {}", groups, value),
+                    None => write!(f, " [{}] = overflow", groups),
+                }
+            }
+            Fields::Operator {
+                length_type_id: 0,
+                length,
+            } => write!(f, " len_type=0 bits={}", length),
+            Fields::Operator { length, .. } => write!(f, " len_type=1 packets={}", length),
+        }
+    }
+}
+
+/// Disassemble the packet starting at `start_index` and append one instruction per packet.
+///
+/// Returns the index after the packet. In contrast to `parse_packet`, the number of operands
+/// is not validated so that malformed transmissions can be inspected.
+fn disassemble_packet(
+    start_index: usize,
+    depth: usize,
+    binary: &[char],
+    listing: &mut Vec<Instruction>,
+) -> Result<usize, DecodeError> {
+    if depth > MAX_DEPTH {
+        return Err(DecodeError::TooDeep {
+            offset: start_index,
+        });
+    }
+    let (version, type_id) = parse_header(start_index, binary)?;
+    let position = listing.len();
+
+    if type_id == 4 {
+        let mut index = start_index + 6;
+        let mut groups = vec![];
+        loop {
+            let group = read_bits(binary, index, 5)?;
+            groups.push(group & 0b1111);
+            index += 5;
+            if group & 0b10000 == 0 {
+                break;
+            }
+        }
+        let value = groups
+            .iter()
+            .try_fold(0u128, |acc, &g| acc.checked_mul(16).map(|n| n | g as u128));
+        listing.push(Instruction {
+            offset: start_index,
+            depth,
+            version,
+            type_id,
+            fields: Fields::Literal { groups, value },
+        });
+        return Ok(index);
+    }
+
+    let length_type_id = read_bits(binary, start_index + 6, 1)?;
+    let (length, mut index) = match length_type_id {
+        0 => (read_bits(binary, start_index + 7, 15)?, start_index + 22),
+        _ => (read_bits(binary, start_index + 7, 11)?, start_index + 18),
+    };
+    listing.push(Instruction {
+        offset: start_index,
+        depth,
+        version,
+        type_id,
+        fields: Fields::Operator {
+            length_type_id,
+            length,
+        },
+    });
+
+    if length_type_id == 0 {
+        let end = index + length;
+        while index < end {
+            index = disassemble_packet(index, depth + 1, binary, listing)?;
+        }
+        if index > end {
+            return Err(DecodeError::SubPacketOverrun {
+                offset: listing[position].offset,
+                end,
+            });
+        }
+    } else {
+        for _ in 0..length {
+            index = disassemble_packet(index, depth + 1, binary, listing)?;
+        }
+    }
+
+    Ok(index)
+}
+
+/// Disassemble the outermost packet of a hexadecimal transmission.
+///
+/// The listing contains one instruction per packet in the order in which they appear
+/// in the transmission. Use the `Display` implementation of `Instruction` to obtain
+/// a human readable listing.
+pub fn disassemble(hex: &str) -> Result<Vec<Instruction>, DecodeError> {
+    let binary = convert_hex_to_binary(hex)?;
+    let mut listing = vec![];
+    disassemble_packet(0, 0, &binary, &mut listing)?;
+    Ok(listing)
+}
+
+/// The notations in which a packet tree can be rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// Infix notation such as `1 + (2 * 3)` or `min(1, 2)`.
+    Infix,
+    /// Prefix notation such as `(+ 1 (* 2 3))`.
+    SExpression,
+}
+
+impl Packet {
+    /// Render the expression described by the packet tree in the given `notation`.
+    pub fn render(&self, notation: Notation) -> String {
+        match notation {
+            Notation::Infix => self.render_infix(false),
+            Notation::SExpression => self.render_sexpression(),
+        }
+    }
+
+    /// Render the packet as s-expression.
+    fn render_sexpression(&self) -> String {
+        match &self.package_type {
+            PacketType::Literal(val) => val.to_string(),
+            PacketType::Operator(packets) => format!(
+                "({} {})",
+                operator_symbol(self.type_id),
+                packets.iter().map(|p| p.render_sexpression()).join(" ")
+            ),
+        }
+    }
+
+    /// Render the packet in infix notation.
+    ///
+    /// Nested binary operations are put in parentheses, so no precedence rules are needed
+    /// to read the result.
+    fn render_infix(&self, nested: bool) -> String {
+        match &self.package_type {
+            PacketType::Literal(val) => val.to_string(),
+            PacketType::Operator(packets) => match self.type_id {
+                2 | 3 => format!(
+                    "{}({})",
+                    operator_symbol(self.type_id),
+                    packets.iter().map(|p| p.render_infix(false)).join(", ")
+                ),
+                _ => {
+                    let separator = format!(" {} ", operator_symbol(self.type_id));
+                    let expression = packets
+                        .iter()
+                        .map(|p| p.render_infix(true))
+                        .join(&separator);
+                    if nested && packets.len() > 1 {
+                        format!("({})", expression)
+                    } else {
+                        expression
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// The symbol used to render the operation associated to a type id.
+fn operator_symbol(type_id: usize) -> &'static str {
+    match type_id {
+        0 => "+",
+        1 => "*",
+        2 => "min",
+        3 => "max",
+        5 => ">",
+        6 => "<",
+        _ => "==",
+    }
+}
+
+/// Decode a transmission and render its expression along with its value.
+///
+/// For example, `C200B40A82` yields `(+ 1 2) = 3` in s-expression notation.
+pub fn pretty_print(hex: &str, notation: Notation) -> Result<String, DecodeError> {
+    let packet = decode(hex)?;
+    match packet.evaluate::<u128>() {
+        Ok(value) => Ok(format!("{} = {}", packet.render(notation), value)),
+        Err(_) => Ok(format!("{} = overflow", packet.render(notation))),
+    }
+}
+
+/// The errors that can occur while compiling an expression into a transmission.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    /// The expression contains a character that is not part of the language.
+    UnexpectedCharacter { position: usize, character: char },
+    /// A token was found where something else was `expected`.
+    UnexpectedToken {
+        position: usize,
+        expected: &'static str,
+    },
+    /// The expression ended where something else was `expected`.
+    UnexpectedEnd { expected: &'static str },
+    /// An integer in the expression does not fit into a `u128`.
+    IntegerOverflow { position: usize },
+    /// The header of a packet does not fit into three bits.
+    InvalidHeader { version: usize, type_id: usize },
+    /// The operator does not have the number of operands its type id requires.
+    InvalidOperandCount { type_id: usize, count: usize },
+    /// The sub-packets of an operator exceed both the 15-bit length and the 11-bit count field.
+    PacketTooLarge { type_id: usize },
+    /// A literal packet has an operator type id or an operator packet has the literal type id.
+    TypeMismatch { type_id: usize },
+    /// Parentheses and functions are nested more than `MAX_DEPTH` levels deep.
+    TooDeep { position: usize },
+    /// The packet is nested more than `MAX_DEPTH` levels deep, so the transmission could
+    /// not be decoded.
+    PacketTooDeep { type_id: usize },
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::UnexpectedCharacter {
+                position,
+                character,
+            } => write!(
+                f,
+                "unexpected character {:?} at position {}",
+                character, position
+            ),
+            CompileError::UnexpectedToken { position, expected } => {
+                write!(f, "expected {} at position {}", expected, position)
+            }
+            CompileError::UnexpectedEnd { expected } => {
+                write!(f, "expected {} at the end of the expression", expected)
+            }
+            CompileError::IntegerOverflow { position } => {
+                write!(f, "integer at position {} overflows", position)
+            }
+            CompileError::InvalidHeader { version, type_id } => write!(
+                f,
+                "version {} and type id {} do not fit into a packet header",
+                version, type_id
+            ),
+            CompileError::InvalidOperandCount { type_id, count } => write!(
+                f,
+                "operator with type id {} cannot have {} operands",
+                type_id, count
+            ),
+            CompileError::PacketTooLarge { type_id } => write!(
+                f,
+                "sub-packets of the operator with type id {} are too large to encode",
+                type_id
+            ),
+            CompileError::TypeMismatch { type_id } => write!(
+                f,
+                "type id {} does not match the contents of the packet",
+                type_id
+            ),
+            CompileError::TooDeep { position } => write!(
+                f,
+                "expression at position {} is nested more than {} levels deep",
+                position, MAX_DEPTH
+            ),
+            CompileError::PacketTooDeep { type_id } => write!(
+                f,
+                "packet with type id {} is nested more than {} levels deep",
+                type_id, MAX_DEPTH
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// The tokens of the expression language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Integer(u128),
+    Plus,
+    Star,
+    Less,
+    Greater,
+    Equal,
+    Min,
+    Max,
+    Open,
+    Close,
+    Comma,
+}
+
+/// Split an expression into tokens along with their character positions.
+fn tokenize(expression: &str) -> Result<Vec<(usize, Token)>, CompileError> {
+    let chars = expression.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut index = 0;
+
+    while index < chars.len() {
+        let start = index;
+        let token = match chars[index] {
+            c if c.is_whitespace() => {
+                index += 1;
+                continue;
+            }
+            c if c.is_ascii_digit() => {
+                let mut number: u128 = 0;
+                while let Some(digit) = chars.get(index).and_then(|c| c.to_digit(10)) {
+                    number = number
+                        .checked_mul(10)
+                        .and_then(|n| n.checked_add(digit as u128))
+                        .ok_or(CompileError::IntegerOverflow { position: start })?;
+                    index += 1;
+                }
+                tokens.push((start, Token::Integer(number)));
+                continue;
+            }
+            c if c.is_ascii_alphabetic() => {
+                while chars.get(index).is_some_and(|c| c.is_ascii_alphanumeric()) {
+                    index += 1;
+                }
+                match chars[start..index].iter().collect::<String>().as_str() {
+                    "min" => tokens.push((start, Token::Min)),
+                    "max" => tokens.push((start, Token::Max)),
+                    _ => {
+                        return Err(CompileError::UnexpectedCharacter {
+                            position: start,
+                            character: c,
+                        })
+                    }
+                }
+                continue;
+            }
+            '=' if chars.get(index + 1) == Some(&'=') => {
+                index += 1;
+                Token::Equal
+            }
+            '+' => Token::Plus,
+            '*' => Token::Star,
+            '<' => Token::Less,
+            '>' => Token::Greater,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            c => {
+                return Err(CompileError::UnexpectedCharacter {
+                    position: start,
+                    character: c,
+                })
+            }
+        };
+        tokens.push((start, token));
+        index += 1;
     }
+
+    Ok(tokens)
 }
 
-/// Parse a generic packet.
-fn parse_packet(start_index: usize, binary: &[char]) -> (Packet, usize) {
-    match binary[start_index + 3..start_index + 6] {
-        ['1', '0', '0'] => parse_literal(start_index, binary),
-        _ => parse_operator(start_index, binary),
+/// A recursive descent parser turning the tokens of an expression into a packet tree.
+///
+/// The grammar, from lowest to highest precedence, reads
+///
+/// ```text
+/// comparison := sum [("<" | ">" | "==") sum]
+/// sum        := product {"+" product}
+/// product    := atom {"*" atom}
+/// atom       := integer | ("min" | "max") "(" comparison {"," comparison} ")" | "(" comparison ")"
+/// ```
+struct ExpressionParser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    /// The number of enclosing parentheses and functions.
+    depth: usize,
+}
+
+impl ExpressionParser {
+    /// Look at the next token without consuming it.
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.index).map(|(_, token)| *token)
+    }
+
+    /// Consume the next token and make sure it is the `expected` one.
+    fn expect(&mut self, token: Token, expected: &'static str) -> Result<(), CompileError> {
+        match self.tokens.get(self.index) {
+            Some((_, t)) if *t == token => {
+                self.index += 1;
+                Ok(())
+            }
+            Some((position, _)) => Err(CompileError::UnexpectedToken {
+                position: *position,
+                expected,
+            }),
+            None => Err(CompileError::UnexpectedEnd { expected }),
+        }
+    }
+
+    /// Create an operator packet, flattening a single operand into the operand itself.
+    fn operator(type_id: usize, mut operands: Vec<Packet>) -> Packet {
+        if operands.len() == 1 {
+            operands.pop().unwrap()
+        } else {
+            Packet {
+                version: 0,
+                type_id,
+                package_type: PacketType::Operator(operands),
+            }
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Packet, CompileError> {
+        let lhs = self.sum()?;
+        let type_id = match self.peek() {
+            Some(Token::Greater) => 5,
+            Some(Token::Less) => 6,
+            Some(Token::Equal) => 7,
+            _ => return Ok(lhs),
+        };
+        self.index += 1;
+        let rhs = self.sum()?;
+        Ok(Packet {
+            version: 0,
+            type_id,
+            package_type: PacketType::Operator(vec![lhs, rhs]),
+        })
+    }
+
+    fn sum(&mut self) -> Result<Packet, CompileError> {
+        let mut operands = vec![self.product()?];
+        while self.peek() == Some(Token::Plus) {
+            self.index += 1;
+            operands.push(self.product()?);
+        }
+        Ok(Self::operator(0, operands))
+    }
+
+    fn product(&mut self) -> Result<Packet, CompileError> {
+        let mut operands = vec![self.atom()?];
+        while self.peek() == Some(Token::Star) {
+            self.index += 1;
+            operands.push(self.atom()?);
+        }
+        Ok(Self::operator(1, operands))
+    }
+
+    /// Parse the contents of a function or parentheses starting at `position`, keeping
+    /// track of the nesting depth.
+    fn nested<F>(&mut self, position: usize, parse: F) -> Result<Packet, CompileError>
+    where
+        F: FnOnce(&mut Self) -> Result<Packet, CompileError>,
+    {
+        if self.depth == MAX_DEPTH {
+            return Err(CompileError::TooDeep { position });
+        }
+        self.depth += 1;
+        let packet = parse(self);
+        self.depth -= 1;
+        packet
+    }
+
+    fn atom(&mut self) -> Result<Packet, CompileError> {
+        let expected = "an integer, a function or an opening parenthesis";
+        let (position, token) = *self
+            .tokens
+            .get(self.index)
+            .ok_or(CompileError::UnexpectedEnd { expected })?;
+        self.index += 1;
+
+        match token {
+            Token::Integer(number) => Ok(Packet {
+                version: 0,
+                type_id: 4,
+                package_type: PacketType::Literal(number),
+            }),
+            Token::Min | Token::Max => self.nested(position, |parser| {
+                parser.expect(Token::Open, "an opening parenthesis")?;
+                let mut operands = vec![parser.comparison()?];
+                while parser.peek() == Some(Token::Comma) {
+                    parser.index += 1;
+                    operands.push(parser.comparison()?);
+                }
+                parser.expect(Token::Close, "a closing parenthesis")?;
+                Ok(Packet {
+                    version: 0,
+                    type_id: if token == Token::Min { 2 } else { 3 },
+                    package_type: PacketType::Operator(operands),
+                })
+            }),
+            Token::Open => self.nested(position, |parser| {
+                let packet = parser.comparison()?;
+                parser.expect(Token::Close, "a closing parenthesis")?;
+                Ok(packet)
+            }),
+            _ => Err(CompileError::UnexpectedToken { position, expected }),
+        }
+    }
+}
+
+/// Parse an arithmetic expression into a packet tree.
+///
+/// The language consists of non-negative integers, `+`, `*`, `min(...)`, `max(...)`,
+/// the comparisons `<`, `>` and `==` as well as parentheses. Comparisons bind weakest and
+/// cannot be chained without parentheses. All packets are given version 0. Parentheses and
+/// functions may be nested at most `MAX_DEPTH` levels deep.
+pub fn parse_expression(expression: &str) -> Result<Packet, CompileError> {
+    let mut parser = ExpressionParser {
+        tokens: tokenize(expression)?,
+        index: 0,
+        depth: 0,
+    };
+    let packet = parser.comparison()?;
+    match parser.tokens.get(parser.index) {
+        None => Ok(packet),
+        Some((position, _)) => Err(CompileError::UnexpectedToken {
+            position: *position,
+            expected: "the end of the expression",
+        }),
+    }
+}
+
+/// Append the `len` lowest bits of `value` to `binary`.
+fn push_bits(binary: &mut Vec<char>, value: usize, len: usize) {
+    (0..len)
+        .rev()
+        .for_each(|b| binary.push(if value >> b & 1 == 1 { '1' } else { '0' }));
+}
+
+/// Append the binary representation of `packet` at nesting level `depth` to `binary`.
+///
+/// Literals use as few groups as possible. Operators use length type 1 unless they have
+/// too many sub-packets for the 11-bit count field.
+fn encode_packet(
+    packet: &Packet,
+    depth: usize,
+    binary: &mut Vec<char>,
+) -> Result<(), CompileError> {
+    if packet.version > 7 || packet.type_id > 7 {
+        return Err(CompileError::InvalidHeader {
+            version: packet.version,
+            type_id: packet.type_id,
+        });
+    }
+    let is_literal = matches!(packet.package_type, PacketType::Literal(_));
+    if is_literal != (packet.type_id == 4) {
+        return Err(CompileError::TypeMismatch {
+            type_id: packet.type_id,
+        });
+    }
+    if depth > MAX_DEPTH {
+        return Err(CompileError::PacketTooDeep {
+            type_id: packet.type_id,
+        });
+    }
+    push_bits(binary, packet.version, 3);
+    push_bits(binary, packet.type_id, 3);
+
+    match &packet.package_type {
+        PacketType::Literal(val) => {
+            let n_groups = (u128::BITS - val.leading_zeros()).max(1).div_ceil(4);
+            for group in (0..n_groups).rev() {
+                push_bits(binary, if group > 0 { 1 } else { 0 }, 1);
+                push_bits(binary, (val >> (4 * group)) as usize & 0b1111, 4);
+            }
+        }
+        PacketType::Operator(packets) => {
+            check_operands(0, packet.type_id, packets.len()).map_err(|_| {
+                CompileError::InvalidOperandCount {
+                    type_id: packet.type_id,
+                    count: packets.len(),
+                }
+            })?;
+            let mut sub_binary = vec![];
+            for p in packets {
+                encode_packet(p, depth + 1, &mut sub_binary)?;
+            }
+            if packets.len() < 1 << 11 {
+                push_bits(binary, 1, 1);
+                push_bits(binary, packets.len(), 11);
+            } else if sub_binary.len() < 1 << 15 {
+                push_bits(binary, 0, 1);
+                push_bits(binary, sub_binary.len(), 15);
+            } else {
+                return Err(CompileError::PacketTooLarge {
+                    type_id: packet.type_id,
+                });
+            }
+            binary.extend(sub_binary);
+        }
     }
+
+    Ok(())
+}
+
+/// Encode a packet tree as hexadecimal transmission, padded with zeros to full hex digits.
+pub fn encode(packet: &Packet) -> Result<String, CompileError> {
+    let mut binary = vec![];
+    encode_packet(packet, 0, &mut binary)?;
+    Ok(binary
+        .chunks(4)
+        .map(|nibble| {
+            let digit = nibble
+                .iter()
+                .chain(std::iter::repeat(&'0'))
+                .take(4)
+                .fold(0, |acc, &b| acc << 1 | if b == '1' { 1 } else { 0 });
+            std::char::from_digit(digit, 16)
+                .unwrap()
+                .to_ascii_uppercase()
+        })
+        .collect())
+}
+
+/// Compile an arithmetic expression into a hexadecimal transmission.
+///
+/// See `parse_expression` for the supported language.
+pub fn compile(expression: &str) -> Result<String, CompileError> {
+    encode(&parse_expression(expression)?)
 }
 
 /// All versions in the packet and its sub-packets.
@@ -221,15 +1298,13 @@ fn sum_versions(packet: &Packet) -> usize {
 
 /// Sum the versions of all packets and sub-packets in the input.
 pub fn day_16_1(data: &[String]) -> usize {
-    let binary = convert_hex_to_binary(&data[0]);
-    let (packet, _) = parse_packet(0, &binary);
+    let packet = decode(&data[0]).expect("Invalid transmission!");
     sum_versions(&packet)
 }
 
 /// Return the value of the input packet.
 pub fn day_16_2(data: &[String]) -> usize {
-    let binary = convert_hex_to_binary(&data[0]);
-    let (packet, _) = parse_packet(0, &binary);
+    let packet = decode(&data[0]).expect("Invalid transmission!");
     packet.value()
 }
 
@@ -237,11 +1312,12 @@ pub fn day_16_2(data: &[String]) -> usize {
 mod tests {
 
     use super::*;
+    use crate::Lcg;
 
     #[test]
     fn test_convert_hex_to_binary() {
         assert_eq!(
-            convert_hex_to_binary("38006F45291200"),
+            convert_hex_to_binary("38006F45291200").unwrap(),
             vec![
                 '0', '0', '1', '1', '1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0',
                 '0', '1', '1', '0', '1', '1', '1', '1', '0', '1', '0', '0', '0', '1', '0', '1',
@@ -261,14 +1337,14 @@ mod tests {
                     '0', '0', '1', '0', '1', '0', '0', '0'
                 ]
             ),
-            (
+            Ok((
                 Packet {
                     version: 6,
                     type_id: 4,
                     package_type: PacketType::Literal(2021)
                 },
                 21
-            )
+            ))
         );
     }
 
@@ -280,7 +1356,7 @@ mod tests {
             '1', '0', '1', '0', '0', '1', '0', '0', '0', '1', '0', '0', '1', '0', '0', '0', '0',
             '0', '0', '0', '0', '0',
         ];
-        let (packet, _) = parse_packet(0, &binary);
+        let (packet, _) = parse_packet(0, 0, &binary).unwrap();
         assert_eq!(packet.version, 1);
         assert_eq!(packet.type_id, 6);
         if let PacketType::Operator(packets) = packet.package_type {
@@ -323,4 +1399,474 @@ mod tests {
         let data = vec!["9C005AC2F8F0".to_string()];
         assert_eq!(day_16_2(&data), 0);
     }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(
+            decode("D2FG28"),
+            Err(DecodeError::InvalidHexDigit {
+                offset: 12,
+                digit: 'G'
+            })
+        );
+        assert_eq!(decode("d2fe28"), decode("D2FE28"));
+
+        // Literal 2021 with its last group cut off
+        assert_eq!(
+            decode("D2FE"),
+            Err(DecodeError::Truncated {
+                offset: 16,
+                needed: 5
+            })
+        );
+
+        // Sum operator (length type 0) declaring 11 bits of sub-packets,
+        // followed by a 21-bit literal
+        assert_eq!(
+            decode("00002F4BF8A"),
+            Err(DecodeError::SubPacketOverrun { offset: 0, end: 33 })
+        );
+
+        // Sum operator (length type 1) without sub-packets
+        assert_eq!(
+            decode("02000"),
+            Err(DecodeError::InvalidOperandCount {
+                offset: 0,
+                type_id: 0,
+                count: 0
+            })
+        );
+
+        // Greater-than operator (length type 1) with a single literal operand
+        assert_eq!(
+            decode("16004428"),
+            Err(DecodeError::InvalidOperandCount {
+                offset: 0,
+                type_id: 5,
+                count: 1
+            })
+        );
+
+        assert_eq!(
+            decode(&format!("D3{}C2", "F".repeat(40))),
+            Err(DecodeError::LiteralOverflow { offset: 0 })
+        );
+
+        assert_eq!(decode(&nested_sums(MAX_DEPTH)).unwrap().value(), 0);
+        assert_eq!(
+            decode(&nested_sums(50_000)),
+            Err(DecodeError::TooDeep {
+                offset: 18 * (MAX_DEPTH + 1)
+            })
+        );
+    }
+
+    /// A transmission of `depth` nested sums with a single operand around the literal 0.
+    fn nested_sums(depth: usize) -> String {
+        // Version 0, type id 0, length type 1 and a single sub-packet
+        let binary = "0000001".to_string() + "00000000001";
+        let binary = binary.repeat(depth) + "00010000000";
+        binary
+            .as_bytes()
+            .chunks(4)
+            .map(|nibble| {
+                let digit = nibble
+                    .iter()
+                    .chain(std::iter::repeat(&b'0'))
+                    .take(4)
+                    .fold(0, |acc, &b| acc << 1 | (b - b'0') as u32);
+                std::char::from_digit(digit, 16).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let listing = disassemble("38006F45291200").unwrap();
+        assert_eq!(listing.len(), 3);
+        assert_eq!(
+            listing[0],
+            Instruction {
+                offset: 0,
+                depth: 0,
+                version: 1,
+                type_id: 6,
+                fields: Fields::Operator {
+                    length_type_id: 0,
+                    length: 27
+                }
+            }
+        );
+        assert_eq!(
+            listing[2],
+            Instruction {
+                offset: 33,
+                depth: 1,
+                version: 2,
+                type_id: 4,
+                fields: Fields::Literal {
+                    groups: vec![1, 4],
+                    value: Some(20)
+                }
+            }
+        );
+        assert_eq!(
+            listing.iter().map(|i| i.to_string()).collect::<Vec<_>>(),
+            vec![
+                "     0:  001 110  lt   v1 len_type=0 bits=27",
+                "    22:  110 100    lit  v6 [1010] = 10",
+                "    33:  010 100    lit  v2 [0001 0100] = 20",
+            ]
+        );
+
+        assert_eq!(
+            disassemble(&nested_sums(50_000)),
+            Err(DecodeError::TooDeep {
+                offset: 18 * (MAX_DEPTH + 1)
+            })
+        );
+    }
+
+    #[test]
+    fn test_pretty_print() {
+        assert_eq!(
+            pretty_print("C200B40A82", Notation::SExpression).unwrap(),
+            "(+ 1 2) = 3"
+        );
+        assert_eq!(
+            pretty_print("9C0141080250320F1802104A08", Notation::SExpression).unwrap(),
+            "(== (+ 1 3) (* 2 2)) = 1"
+        );
+        assert_eq!(
+            pretty_print("9C0141080250320F1802104A08", Notation::Infix).unwrap(),
+            "(1 + 3) == (2 * 2) = 1"
+        );
+        assert_eq!(
+            pretty_print("880086C3E88112", Notation::Infix).unwrap(),
+            "min(7, 8, 9) = 7"
+        );
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        assert_eq!(
+            parse_expression("1 + 2 * 3")
+                .unwrap()
+                .render(Notation::SExpression),
+            "(+ 1 (* 2 3))"
+        );
+        assert_eq!(
+            parse_expression("min(4, (1 + 2) * 3) == max(4,2)")
+                .unwrap()
+                .render(Notation::SExpression),
+            "(== (min 4 (* (+ 1 2) 3)) (max 4 2))"
+        );
+        assert_eq!(
+            parse_expression("1 + x"),
+            Err(CompileError::UnexpectedCharacter {
+                position: 4,
+                character: 'x'
+            })
+        );
+        assert_eq!(
+            parse_expression("1 < 2 < 3"),
+            Err(CompileError::UnexpectedToken {
+                position: 6,
+                expected: "the end of the expression"
+            })
+        );
+        assert_eq!(
+            parse_expression("max(1,"),
+            Err(CompileError::UnexpectedEnd {
+                expected: "an integer, a function or an opening parenthesis"
+            })
+        );
+
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(parse_expression(&nested(MAX_DEPTH)), parse_expression("1"));
+        assert_eq!(
+            parse_expression(&nested(50_000)),
+            Err(CompileError::TooDeep {
+                position: MAX_DEPTH
+            })
+        );
+    }
+
+    #[test]
+    fn test_compile() {
+        let data = vec![compile("(1 + 3) == (2 * 2)").unwrap()];
+        assert_eq!(day_16_2(&data), 1);
+        assert_eq!(
+            pretty_print(&data[0], Notation::Infix).unwrap(),
+            "(1 + 3) == (2 * 2) = 1"
+        );
+        assert_eq!(compile("2021").unwrap(), "12FE28");
+        assert_eq!(compile("0").unwrap(), "100");
+
+        // Each level of parentheses nests a product inside a sum
+        let expression = (0..MAX_DEPTH).fold("1".to_string(), |e, _| format!("1 + 2 * ({})", e));
+        assert_eq!(
+            compile(&expression),
+            Err(CompileError::PacketTooDeep { type_id: 4 })
+        );
+    }
+
+    #[test]
+    fn test_encode_type_mismatch() {
+        let literal = |type_id| Packet {
+            version: 0,
+            type_id,
+            package_type: PacketType::Literal(1),
+        };
+
+        // An operator with the literal type id would be decoded as a literal
+        let packet = Packet {
+            version: 0,
+            type_id: 4,
+            package_type: PacketType::Operator(vec![literal(4)]),
+        };
+        assert_eq!(
+            encode(&packet),
+            Err(CompileError::TypeMismatch { type_id: 4 })
+        );
+
+        // A literal with an operator type id would be decoded as an operator
+        let packet = Packet {
+            version: 0,
+            type_id: 1,
+            package_type: PacketType::Operator(vec![literal(0)]),
+        };
+        assert_eq!(
+            encode(&packet),
+            Err(CompileError::TypeMismatch { type_id: 0 })
+        );
+
+        assert_eq!(
+            encode(&literal(0)),
+            Err(CompileError::TypeMismatch { type_id: 0 })
+        );
+        assert_eq!(decode(&encode(&literal(4)).unwrap()), Ok(literal(4)));
+    }
+
+    /// Generate a random expression of at most the given nesting `depth`.
+    ///
+    /// Returns the expression, its value computed independently of the parser (or `None` if
+    /// it overflows a `u128`), and its precedence: 0 for comparisons, 1 for sums, 2 for
+    /// products and 3 for literals and functions. Parentheses are only added where the
+    /// precedence requires them, or at random.
+    fn random_expression(random: &mut Lcg, depth: usize) -> (String, Option<u128>, usize) {
+        if depth == 0 || random.next_below(4) == 0 {
+            let literal = random.next_below(1000);
+            return (literal.to_string(), Some(literal as u128), 3);
+        }
+        let operands = (0..2 + random.next_below(2))
+            .map(|_| random_expression(random, depth - 1))
+            .collect::<Vec<_>>();
+        let values = operands.iter().map(|(_, value, _)| *value);
+        let operator = random.next_below(7);
+        // Parenthesize operands binding less tightly than the operator, or at random
+        let mut wrap = |precedence: usize| {
+            operands
+                .iter()
+                .map(|(expression, _, p)| {
+                    if *p < precedence || random.next_below(8) == 0 {
+                        format!("({})", expression)
+                    } else {
+                        expression.clone()
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        let compare = |ordering: fn(&u128, &u128) -> bool| {
+            let values = values.clone().take(2).collect::<Option<Vec<_>>>()?;
+            Some(ordering(&values[0], &values[1]) as u128)
+        };
+        match operator {
+            0 => (
+                wrap(1).join(" + "),
+                values.clone().try_fold(0u128, |acc, v| acc.checked_add(v?)),
+                1,
+            ),
+            1 => (
+                wrap(2).join(" * "),
+                values.clone().try_fold(1u128, |acc, v| acc.checked_mul(v?)),
+                2,
+            ),
+            2 => (
+                format!("min({})", wrap(0).join(", ")),
+                values
+                    .clone()
+                    .collect::<Option<Vec<_>>>()
+                    .map(|v| *v.iter().min().unwrap()),
+                3,
+            ),
+            3 => (
+                format!("max({})", wrap(0).join(", ")),
+                values
+                    .clone()
+                    .collect::<Option<Vec<_>>>()
+                    .map(|v| *v.iter().max().unwrap()),
+                3,
+            ),
+            // Comparisons do not associate, so operands that are comparisons themselves are
+            // parenthesized
+            4 => {
+                let operands = wrap(1);
+                (
+                    format!("{} < {}", operands[0], operands[1]),
+                    compare(u128::lt),
+                    0,
+                )
+            }
+            5 => {
+                let operands = wrap(1);
+                (
+                    format!("{} > {}", operands[0], operands[1]),
+                    compare(u128::gt),
+                    0,
+                )
+            }
+            _ => {
+                let operands = wrap(1);
+                (
+                    format!("{} == {}", operands[0], operands[1]),
+                    compare(u128::eq),
+                    0,
+                )
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile_roundtrip() {
+        let mut random = Lcg::new(2021);
+        let mut checked = 0;
+        for _ in 0..500 {
+            let (expression, value, _) = random_expression(&mut random, 3);
+            let packet = parse_expression(&expression).unwrap();
+            let data = vec![compile(&expression).unwrap()];
+            assert_eq!(decode(&data[0]).unwrap(), packet, "{}", expression);
+            // Compare against the value of the expression itself, not of the parsed packet
+            if let Some(value) = value.and_then(|v| usize::try_from(v).ok()) {
+                assert_eq!(day_16_2(&data), value, "{}", expression);
+                checked += 1;
+            }
+        }
+        assert!(checked > 400);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        // A literal with 68 bits
+        let packet = decode("D3FFFFFFFFFFFFFFFFFFFC2").unwrap();
+        assert_eq!(
+            packet.evaluate::<u64>(),
+            Err(EvaluateError::Overflow { type_id: 4 })
+        );
+        assert_eq!(packet.evaluate::<u128>(), Ok((1 << 68) - 15));
+
+        let packet = parse_expression("4294967296 * 4294967296 + 1").unwrap();
+        assert_eq!(
+            packet.evaluate::<u64>(),
+            Err(EvaluateError::Overflow { type_id: 1 })
+        );
+        assert_eq!(packet.evaluate::<u128>(), Ok((1 << 64) + 1));
+
+        let packet = parse_expression("18446744073709551615 + 1").unwrap();
+        assert_eq!(
+            packet.evaluate::<u64>(),
+            Err(EvaluateError::Overflow { type_id: 0 })
+        );
+        assert_eq!(
+            pretty_print(&encode(&packet).unwrap(), Notation::Infix).unwrap(),
+            "18446744073709551615 + 1 = 18446744073709551616"
+        );
+
+        let data = ["9C0141080250320F1802104A08".to_string()];
+        assert_eq!(decode(&data[0]).unwrap().evaluate::<u64>(), Ok(1));
+
+        // Packets built by hand are not validated
+        let operator = |type_id, operands| Packet {
+            version: 0,
+            type_id,
+            package_type: PacketType::Operator(operands),
+        };
+        let literal = Packet {
+            version: 0,
+            type_id: 4,
+            package_type: PacketType::Literal(1),
+        };
+        assert_eq!(
+            operator(2, vec![]).evaluate::<u64>(),
+            Err(EvaluateError::InvalidOperandCount {
+                type_id: 2,
+                count: 0
+            })
+        );
+        assert_eq!(
+            operator(5, vec![literal]).evaluate::<u64>(),
+            Err(EvaluateError::InvalidOperandCount {
+                type_id: 5,
+                count: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_packet_stream() {
+        // Two transmissions on one line and three more with trailing padding on separate lines
+        let input = "12FE2812FE28\n38006F45291200\r\n\nC200B40A82\n880086C3E88112";
+        let values = PacketStream::new(input.as_bytes())
+            .map(|p| p.unwrap().value())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![2021, 2021, 1, 3, 7]);
+
+        // A transmission that needs several reads to be decoded
+        let expression = (1..=1000).map(|i| i.to_string()).join(" + ");
+        let input = format!(
+            "{}\n{}",
+            compile(&expression).unwrap(),
+            compile("7").unwrap()
+        );
+        let values = PacketStream::new(input.as_bytes())
+            .map(|p| p.unwrap().value())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![500500, 7]);
+
+        // A transmission wrapped over several lines, followed by another one
+        let input = "9C0141080250\n320F18\r\n02104A08\n12FE28";
+        let values = PacketStream::new(input.as_bytes())
+            .map(|p| p.unwrap().value())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![1, 2021]);
+
+        let mut stream = PacketStream::new("12FE28\nD2FE".as_bytes());
+        assert_eq!(stream.next().unwrap().unwrap().value(), 2021);
+        match stream.next() {
+            Some(Err(StreamError::Decode(e))) => {
+                assert_eq!(
+                    e,
+                    DecodeError::Truncated {
+                        offset: 40,
+                        needed: 5
+                    }
+                )
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert!(stream.next().is_none());
+
+        let mut stream = PacketStream::new("12FE28 12FG".as_bytes());
+        assert!(stream.next().unwrap().is_ok());
+        match stream.next() {
+            Some(Err(StreamError::Decode(e))) => assert_eq!(
+                e,
+                DecodeError::InvalidHexDigit {
+                    offset: 36,
+                    digit: 'G'
+                }
+            ),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
 }
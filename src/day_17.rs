@@ -40,80 +40,473 @@
 //!
 //! In the equation for the y direction, vy0 < 0 to immediately reach the lower bound after one step. Hence the absolute value
 //! of the velocity |vy| < - y_min.
+//!
+//! For general target areas, the probe never changes its horizontal direction, so the initial
+//! horizontal velocity lies between `min(0, x_min)` and `max(0, x_max)`. A probe launched
+//! upwards with vy0 passes y=0 again with velocity -(vy0 + 1), so |vy0| is bounded by the
+//! largest absolute value of `y_min` and `y_max`, unless the target contains the row y=0.
+//! Then the probe may hit the target at step 2 * vy0 + 1 when it passes y=0. If it has come
+//! to a horizontal halt by then, the target is hit by arbitrarily large vertical velocities.
+//! Otherwise it has moved at least (2 * vy0 + 1) * (2 * vy0 + 2) / 2 horizontally, which
+//! bounds vy0 by roughly sqrt(2 * max(|x_min|, |x_max|)) / 2.
 use itertools::Itertools;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+lazy_static::lazy_static! {
+    static ref RE: Regex =
+        Regex::new(r"^target area: x=(-?\d+)\.\.(-?\d+), y=(-?\d+)\.\.(-?\d+)$").unwrap();
+}
+
+/// The rectangular target area the probe has to hit, including its bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetArea {
+    pub x_min: isize,
+    pub x_max: isize,
+    pub y_min: isize,
+    pub y_max: isize,
+}
+
+impl TargetArea {
+    /// Check whether the point `(x, y)` lies within the target area.
+    fn contains(&self, x: isize, y: isize) -> bool {
+        y >= self.y_min && y <= self.y_max && x >= self.x_min && x <= self.x_max
+    }
+}
+
+/// The reasons why no trick shot into a target area can be determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrickShotError {
+    /// No initial velocity makes the probe hit the target area.
+    ///
+    /// Since the probe can reach any point after the first step, this only happens for
+    /// empty target areas.
+    NoHit,
+    /// Arbitrarily large vertical velocities make the probe hit the target area.
+    Unbounded,
+}
+
+impl fmt::Display for TrickShotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrickShotError::NoHit => write!(f, "no initial velocity hits the target area"),
+            TrickShotError::Unbounded => {
+                write!(f, "infinitely many initial velocities hit the target area")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TrickShotError {}
+
+/// The errors that can occur while parsing a `TargetArea`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseTargetAreaError {
+    /// The line does not have the form `target area: x=20..30, y=-10..-5`.
+    InvalidFormat(String),
+    /// A bound does not fit into an `isize`.
+    InvalidNumber(String),
+    /// The lower bound of the range for `axis` is larger than its upper bound.
+    EmptyRange { axis: char, min: isize, max: isize },
+}
+
+impl fmt::Display for ParseTargetAreaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseTargetAreaError::InvalidFormat(s) => write!(f, "invalid target area {:?}", s),
+            ParseTargetAreaError::InvalidNumber(s) => write!(f, "invalid bound {:?}", s),
+            ParseTargetAreaError::EmptyRange { axis, min, max } => {
+                write!(f, "empty {} range {}..{}", axis, min, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseTargetAreaError {}
+
+impl FromStr for TargetArea {
+    type Err = ParseTargetAreaError;
+
+    /// Parse the target area from its description in the input, e.g.
+    ///
+    /// ```sh
+    /// target area: x=20..30, y=-10..-5
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let captures = RE
+            .captures(s.trim())
+            .ok_or_else(|| ParseTargetAreaError::InvalidFormat(s.to_string()))?;
+        let bound = |i: usize| {
+            let bound = captures.get(i).unwrap().as_str();
+            bound
+                .parse::<isize>()
+                .map_err(|_| ParseTargetAreaError::InvalidNumber(bound.to_string()))
+        };
+        let target = TargetArea {
+            x_min: bound(1)?,
+            x_max: bound(2)?,
+            y_min: bound(3)?,
+            y_max: bound(4)?,
+        };
+        if target.x_min > target.x_max {
+            return Err(ParseTargetAreaError::EmptyRange {
+                axis: 'x',
+                min: target.x_min,
+                max: target.x_max,
+            });
+        }
+        if target.y_min > target.y_max {
+            return Err(ParseTargetAreaError::EmptyRange {
+                axis: 'y',
+                min: target.y_min,
+                max: target.y_max,
+            });
+        }
+        Ok(target)
+    }
+}
+
+/// The state of the probe after a number of steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeState {
+    /// The number of steps taken since the launch.
+    pub step: usize,
+    pub x: isize,
+    pub y: isize,
+    /// The horizontal velocity applied in the next step.
+    pub vx: isize,
+    /// The vertical velocity applied in the next step.
+    pub vy: isize,
+}
+
+/// An infinite iterator over the states of a probe after each step.
+pub struct Trajectory {
+    state: ProbeState,
+}
+
+impl Iterator for Trajectory {
+    type Item = ProbeState;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let state = &mut self.state;
+        state.step += 1;
+        state.x += state.vx;
+        state.y += state.vy;
+        state.vx -= state.vx.signum();
+        state.vy -= 1;
+        Some(*state)
+    }
+}
+
+/// The trajectory of a probe launched from the origin with initial velocity `(vx, vy)`.
+///
+/// The launch position itself is not part of the trajectory.
+pub fn trajectory(vx: isize, vy: isize) -> Trajectory {
+    Trajectory {
+        state: ProbeState {
+            step: 0,
+            x: 0,
+            y: 0,
+            vx,
+            vy,
+        },
+    }
+}
+
+/// The states of a probe until it either hits the target area or falls past it.
+///
+/// The state in which the target is hit is the last one.
+fn flight(vx: isize, vy: isize, target: &TargetArea) -> impl Iterator<Item = ProbeState> + '_ {
+    let mut done = false;
+    trajectory(vx, vy)
+        // Once the probe falls below the target area, it cannot come back
+        .take_while(move |s| !(s.y < target.y_min && s.vy <= 0))
+        .take_while(move |s| {
+            let take = !done;
+            done = target.contains(s.x, s.y);
+            take
+        })
+}
+
+/// A trick shot that hits the target area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hit {
+    /// The initial velocity `(vx, vy)`.
+    pub velocity: (isize, isize),
+    /// The step at which the probe enters the target area.
+    pub step: usize,
+    /// The highest y position on the trajectory.
+    pub apex: isize,
+}
 
 /// Simulate the evolution of the system for a given set of initial conditions and the target area.
 ///
-/// If the probe hits the target area for the given initial condition the maximal y value is returned
+/// If the probe hits the target area for the given initial condition the `Hit` is returned
 /// in an `Option`, otherwise `None`.
-fn simulate(
-    vx: isize,
-    vy: isize,
-    x_min: isize,
-    x_max: isize,
-    y_min: isize,
-    y_max: isize,
-) -> Option<isize> {
-    // Initial position
-    let (mut x, mut y) = (0, 0);
-
-    // Initial velocity
-    let (mut vx, mut vy) = (vx, vy);
-
-    // Track maximal y position
-    let mut current_y_max = y;
-
-    loop {
-        x += vx;
-        y += vy;
-        vx += match vx {
-            v if v > 0 => -1,
-            v if v < 0 => 1,
-            _ => 0,
-        };
-        vy -= 1;
+fn simulate(vx: isize, vy: isize, target: &TargetArea) -> Option<Hit> {
+    flight(vx, vy, target)
+        .find(|s| target.contains(s.x, s.y))
+        .map(|s| Hit {
+            velocity: (vx, vy),
+            step: s.step,
+            apex: if vy > 0 { vy * (vy + 1) / 2 } else { 0 },
+        })
+}
 
-        if y >= current_y_max {
-            current_y_max = y;
-        }
+/// Draw the trajectory of a probe with initial velocity `(vx, vy)` like in the puzzle description.
+///
+/// The launch position is marked by `S`, the positions of the probe by `#` and the target area
+/// by `T`. The trajectory is drawn until the probe hits the target or falls past it.
+pub fn plot(vx: isize, vy: isize, target: &TargetArea) -> String {
+    let positions = flight(vx, vy, target)
+        .map(|s| (s.x, s.y))
+        .collect::<HashSet<_>>();
 
-        // If we enter the target area, we are done
-        if y >= y_min && y <= y_max && x >= x_min && x <= x_max {
-            return Some(current_y_max);
-        }
+    let x_min = positions
+        .iter()
+        .map(|p| p.0)
+        .fold(target.x_min.min(0), isize::min);
+    let x_max = positions
+        .iter()
+        .map(|p| p.0)
+        .fold(target.x_max.max(0), isize::max);
+    let y_min = positions
+        .iter()
+        .map(|p| p.1)
+        .fold(target.y_min.min(0), isize::min);
+    let y_max = positions
+        .iter()
+        .map(|p| p.1)
+        .fold(target.y_max.max(0), isize::max);
+
+    (y_min..=y_max)
+        .rev()
+        .map(|y| {
+            (x_min..=x_max)
+                .map(|x| match (x, y) {
+                    (0, 0) => 'S',
+                    p if positions.contains(&p) => '#',
+                    (x, y) if target.contains(x, y) => 'T',
+                    _ => '.',
+                })
+                .collect::<String>()
+        })
+        .join("\n")
+}
+
+/// Check whether arbitrarily large vertical velocities hit the target area.
+///
+/// This is the case if the target contains the row y=0, which the probe passes again after
+/// rising, and a position at which the probe comes to a horizontal halt.
+fn is_unbounded(target: &TargetArea) -> bool {
+    target.y_min <= 0
+        && target.y_max >= 0
+        && (target.x_min.min(0)..=target.x_max.max(0)).any(|vx| {
+            let halt = vx.signum() * vx.abs() * (vx.abs() + 1) / 2;
+            halt >= target.x_min && halt <= target.x_max
+        })
+}
 
-        if y < y_min {
-            break;
+/// The vertical velocities that may hit a target area which is not unbounded.
+fn vertical_velocities(target: &TargetArea) -> std::ops::RangeInclusive<isize> {
+    let mut vyend = target.y_min.abs().max(target.y_max.abs());
+    if target.y_min <= 0 && target.y_max >= 0 {
+        // The probe passes y=0 at step t = 2 * vy + 1, having moved at least t * (t + 1) / 2
+        let x_reach = target.x_min.abs().max(target.x_max.abs());
+        let passes = |vy: isize| {
+            let t = 2 * vy + 1;
+            t * (t + 1) / 2 <= x_reach
+        };
+        while passes(vyend + 1) {
+            vyend += 1;
         }
     }
+    target.y_min.min(0)..=vyend
+}
+
+/// List all initial velocities for which the probe hits the target area.
+///
+/// The hits are ordered by their horizontal and then by their vertical velocity.
+pub fn hitting_velocities(target: &TargetArea) -> Result<Vec<Hit>, TrickShotError> {
+    if is_unbounded(target) {
+        return Err(TrickShotError::Unbounded);
+    }
+
+    let vxstart = target.x_min.min(0);
+    let vxend = target.x_max.max(0);
+    let hits = (vxstart..=vxend)
+        .cartesian_product(vertical_velocities(target))
+        .filter_map(|(vx, vy)| simulate(vx, vy, target))
+        .collect::<Vec<_>>();
 
-    None
+    if hits.is_empty() {
+        Err(TrickShotError::NoHit)
+    } else {
+        Ok(hits)
+    }
 }
 
 /// Compute the maximal y-value for those trajectories that reach the target area.
-pub fn day_17_1(x_min: isize, x_max: isize, y_min: isize, y_max: isize) -> isize {
-    let vxstart = (x_min as f64).sqrt() as isize;
-    let vystart = 0;
-    let vxend = x_max + 1;
-    let vyend = -y_min;
+pub fn max_height(target: &TargetArea) -> Result<isize, TrickShotError> {
+    hitting_velocities(target).map(|hits| hits.into_iter().map(|h| h.apex).max().unwrap())
+}
+
+/// Count the initial velocities for which the probe reaches the target area.
+pub fn count_velocities(target: &TargetArea) -> Result<usize, TrickShotError> {
+    hitting_velocities(target).map(|hits| hits.len())
+}
 
-    (vxstart..=vxend)
-        .cartesian_product(vystart..=vyend)
-        .filter_map(|(vx, vy)| simulate(vx, vy, x_min, x_max, y_min, y_max))
-        .max()
-        .unwrap()
+/// The smallest `t` in `lo..=hi` for which `pred` holds, or `hi + 1` if there is none.
+///
+/// The predicate must be monotone, i.e. once it holds it has to hold for all larger `t`.
+fn first_step(lo: isize, hi: isize, pred: impl Fn(isize) -> bool) -> isize {
+    let (mut lo, mut hi) = (lo, hi + 1);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(mid) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    lo
+}
+
+/// The distance from the launcher after `t` steps of a probe with horizontal velocity `v >= 0`.
+fn x_position(v: isize, t: isize) -> isize {
+    let t = t.min(v);
+    v * t - t * (t - 1) / 2
+}
+
+/// The height after `t` steps of a probe with vertical velocity `v`.
+fn y_position(v: isize, t: isize) -> isize {
+    v * t - t * (t - 1) / 2
+}
+
+/// Compute the time windows during which probes moving away from the launcher are within
+/// the horizontal distance `lo..=hi`.
+///
+/// Each window `(first, last)` contains the first and the last step in the target range,
+/// where `last` is `isize::MAX` if the probe comes to a halt within the range. The windows
+/// are ordered by increasing velocity, for which both `first` and `last` do not increase.
+/// Velocities that skip the range entirely are omitted.
+fn x_windows(lo: isize, hi: isize) -> Vec<(isize, isize)> {
+    (1..=hi)
+        .filter_map(|v| {
+            let halt = x_position(v, v);
+            if halt < lo {
+                return None;
+            }
+            let first = first_step(1, v, |t| x_position(v, t) >= lo);
+            let last = if halt <= hi {
+                isize::MAX
+            } else {
+                first_step(0, v, |t| x_position(v, t) > hi) - 1
+            };
+            (first <= last).then_some((first, last))
+        })
+        .collect()
+}
+
+/// Count the windows of a family created by `x_windows` that overlap with the steps `c..=d`.
+fn count_overlapping(windows: &[(isize, isize)], c: isize, d: isize) -> usize {
+    // Windows starting too late form a prefix, those ending early enough a suffix
+    let start = windows.partition_point(|&(first, _)| first > d);
+    let end = windows.partition_point(|&(_, last)| last >= c);
+    end.saturating_sub(start)
+}
+
+/// Compute the time windows during which a probe with vertical velocity `vy` is within the
+/// heights of the target area.
+///
+/// The height increases up to step `max(vy, 0)` and decreases afterwards, so there is at most
+/// one window while rising and one while falling.
+fn y_windows(vy: isize, target: &TargetArea) -> Vec<(isize, isize)> {
+    let apex = vy.max(0);
+    let mut windows = vec![];
+
+    let first = first_step(1, apex, |t| y_position(vy, t) >= target.y_min);
+    let last = first_step(1, apex, |t| y_position(vy, t) > target.y_max) - 1;
+    if first <= last {
+        windows.push((first, last));
+    }
+
+    // Find a step at which the probe has fallen below the target
+    let mut end = apex + 1;
+    while y_position(vy, end) >= target.y_min {
+        end = apex + 2 * (end - apex);
+    }
+    let first = first_step(apex + 1, end, |t| y_position(vy, t) <= target.y_max);
+    let last = first_step(apex + 1, end, |t| y_position(vy, t) < target.y_min) - 1;
+    if first <= last {
+        windows.push((first, last));
+    }
+
+    windows
 }
 
-pub fn day_17_2(x_min: isize, x_max: isize, y_min: isize, y_max: isize) -> usize {
-    let vxstart = (x_min as f64).sqrt() as isize;
-    let vystart = y_min;
-    let vxend = x_max + 1;
-    let vyend = -y_min;
+/// Count the initial velocities for which the probe reaches the target area without
+/// simulating the trajectories.
+///
+/// The time windows in which the probe is within the target range are computed for each
+/// horizontal and each vertical velocity independently from the closed-form motion. A pair
+/// of velocities hits the target if its windows overlap. Since the horizontal windows are
+/// ordered, the overlapping ones are counted by binary search for each vertical velocity.
+pub fn count_velocities_analytic(target: &TargetArea) -> Result<usize, TrickShotError> {
+    if is_unbounded(target) {
+        return Err(TrickShotError::Unbounded);
+    }
+    if target.x_min > target.x_max || target.y_min > target.y_max {
+        return Err(TrickShotError::NoHit);
+    }
+
+    // Velocities to the right, to the left and straight up
+    let families = [
+        x_windows(target.x_min, target.x_max),
+        x_windows(-target.x_max, -target.x_min),
+        if target.x_min <= 0 && target.x_max >= 0 {
+            vec![(1, isize::MAX)]
+        } else {
+            vec![]
+        },
+    ];
+
+    let count = vertical_velocities(target)
+        .map(|vy| {
+            let windows = y_windows(vy, target);
+            families
+                .iter()
+                .map(|family| {
+                    let hits = windows
+                        .iter()
+                        .map(|&(c, d)| count_overlapping(family, c, d))
+                        .sum::<usize>();
+                    // Do not count horizontal windows overlapping both vertical ones twice
+                    match windows[..] {
+                        [(_, d1), (c2, _)] => hits - count_overlapping(family, c2, d1),
+                        _ => hits,
+                    }
+                })
+                .sum::<usize>()
+        })
+        .sum();
 
-    (vxstart..=vxend)
-        .cartesian_product(vystart..=vyend)
-        .filter_map(|(vx, vy)| simulate(vx, vy, x_min, x_max, y_min, y_max))
-        .count()
+    match count {
+        0 => Err(TrickShotError::NoHit),
+        count => Ok(count),
+    }
+}
+
+/// Compute the maximal y-value for those trajectories that reach the target area in the input.
+pub fn day_17_1(data: &[String]) -> isize {
+    max_height(&data[0].parse().unwrap()).unwrap()
+}
+
+/// Count the initial velocities for which the probe reaches the target area in the input.
+pub fn day_17_2(data: &[String]) -> usize {
+    count_velocities_analytic(&data[0].parse().unwrap()).unwrap()
 }
 
 #[cfg(test)]
@@ -121,21 +514,312 @@ mod tests {
 
     use super::*;
 
+    const EXAMPLE: TargetArea = TargetArea {
+        x_min: 20,
+        x_max: 30,
+        y_min: -10,
+        y_max: -5,
+    };
+
+    #[test]
+    fn test_parse_target_area() {
+        assert_eq!(
+            "target area: x=20..30, y=-10..-5".parse::<TargetArea>(),
+            Ok(EXAMPLE)
+        );
+        assert_eq!(
+            "target area: x=20..30".parse::<TargetArea>(),
+            Err(ParseTargetAreaError::InvalidFormat(
+                "target area: x=20..30".to_string()
+            ))
+        );
+        assert_eq!(
+            "target area: x=20..30, y=-5..-10".parse::<TargetArea>(),
+            Err(ParseTargetAreaError::EmptyRange {
+                axis: 'y',
+                min: -5,
+                max: -10
+            })
+        );
+        assert_eq!(
+            "target area: x=0..99999999999999999999, y=-5..-1".parse::<TargetArea>(),
+            Err(ParseTargetAreaError::InvalidNumber(
+                "99999999999999999999".to_string()
+            ))
+        );
+    }
+
     #[test]
     fn test_simulate() {
-        assert_eq!(simulate(7, 2, 20, 30, -10, -5), Some(3));
-        assert_eq!(simulate(6, 3, 20, 30, -10, -5), Some(6));
-        assert_eq!(simulate(9, 0, 20, 30, -10, -5), Some(0));
-        assert_eq!(simulate(6, 9, 20, 30, -10, -5), Some(45));
+        let apex = |vx, vy| simulate(vx, vy, &EXAMPLE).map(|h| h.apex);
+        assert_eq!(apex(7, 2), Some(3));
+        assert_eq!(apex(6, 3), Some(6));
+        assert_eq!(apex(9, 0), Some(0));
+        assert_eq!(apex(6, 9), Some(45));
+        assert_eq!(apex(17, -4), None);
     }
 
     #[test]
     fn test_day_17_1() {
-        assert_eq!(day_17_1(20, 30, -10, -5), 45);
+        let data = vec!["target area: x=20..30, y=-10..-5".to_string()];
+        assert_eq!(day_17_1(&data), 45);
     }
 
     #[test]
     fn test_day_17_2() {
-        assert_eq!(day_17_2(20, 30, -10, -5), 112);
+        let data = vec!["target area: x=20..30, y=-10..-5".to_string()];
+        assert_eq!(day_17_2(&data), 112);
+    }
+
+    /// Count the hitting velocities by brute force over all velocities up to `bound`.
+    fn count_brute_force(target: &TargetArea, bound: isize) -> usize {
+        (-bound..=bound)
+            .cartesian_product(-bound..=bound)
+            .filter(|&(vx, vy)| {
+                trajectory(vx, vy)
+                    .take(2 * bound as usize + 50)
+                    .any(|s| target.contains(s.x, s.y))
+            })
+            .count()
+    }
+
+    #[test]
+    fn test_general_targets() {
+        let targets = [
+            EXAMPLE,
+            // Left of the launcher
+            TargetArea {
+                x_min: -30,
+                x_max: -20,
+                y_min: -10,
+                y_max: -5,
+            },
+            // Straddling x=0
+            TargetArea {
+                x_min: -3,
+                x_max: 4,
+                y_min: -10,
+                y_max: -5,
+            },
+            // Above the launcher
+            TargetArea {
+                x_min: 20,
+                x_max: 30,
+                y_min: 5,
+                y_max: 10,
+            },
+            // Above and left of the launcher
+            TargetArea {
+                x_min: -12,
+                x_max: -7,
+                y_min: 30,
+                y_max: 40,
+            },
+        ];
+        for target in targets.iter() {
+            assert_eq!(
+                count_velocities(target),
+                Ok(count_brute_force(target, 100)),
+                "{:?}",
+                target
+            );
+            assert_eq!(
+                count_velocities_analytic(target),
+                Ok(count_brute_force(target, 100)),
+                "{:?}",
+                target
+            );
+        }
+
+        // Fast probes may hit a target containing y=0 when passing it on the way down
+        let target = TargetArea {
+            x_min: 92,
+            x_max: 104,
+            y_min: -1,
+            y_max: 1,
+        };
+        assert_eq!(count_brute_force(&target, 110), 61);
+        assert_eq!(count_velocities(&target), Ok(61));
+        assert_eq!(count_velocities_analytic(&target), Ok(61));
+        assert_eq!(max_height(&target), Ok(21));
+
+        assert_eq!(
+            max_height(&TargetArea {
+                x_min: -30,
+                x_max: -20,
+                y_min: -10,
+                y_max: -5,
+            }),
+            Ok(45)
+        );
+        assert_eq!(
+            max_height(&TargetArea {
+                x_min: 20,
+                x_max: 30,
+                y_min: 5,
+                y_max: 10,
+            }),
+            Ok(55)
+        );
+    }
+
+    #[test]
+    fn test_no_trick_shot() {
+        // Empty target areas cannot be parsed, but constructed
+        let target = TargetArea {
+            x_min: 2,
+            x_max: 1,
+            y_min: 100,
+            y_max: 101,
+        };
+        assert_eq!(max_height(&target), Err(TrickShotError::NoHit));
+        assert_eq!(count_velocities(&target), Err(TrickShotError::NoHit));
+        assert_eq!(
+            count_velocities_analytic(&target),
+            Err(TrickShotError::NoHit)
+        );
+
+        let target = TargetArea {
+            x_min: 5,
+            x_max: 7,
+            y_min: -2,
+            y_max: 2,
+        };
+        assert_eq!(max_height(&target), Err(TrickShotError::Unbounded));
+        assert_eq!(
+            count_velocities_analytic(&target),
+            Err(TrickShotError::Unbounded)
+        );
+    }
+
+    #[test]
+    fn test_count_velocities_analytic() {
+        for (x_min, x_max, y_min, y_max) in [
+            (20, 30, -10, -5),
+            (195, 238, -93, -67),
+            (-50, -1, -40, 3),
+            (-7, 9, 1, 60),
+            (3, 3, 6, 6),
+            (90, 150, 40, 120),
+            (92, 104, -1, 1),
+            (40, 44, -3, 2),
+        ] {
+            let target = TargetArea {
+                x_min,
+                x_max,
+                y_min,
+                y_max,
+            };
+            assert_eq!(
+                count_velocities_analytic(&target),
+                count_velocities(&target),
+                "{:?}",
+                target
+            );
+        }
+
+        // Cross-check against brute force independently of the shared velocity bounds
+        for (x_min, x_max, y_min, y_max) in [
+            (20, 30, -10, -5),
+            (-7, 9, 1, 60),
+            (3, 3, 6, 6),
+            (92, 104, -1, 1),
+            (40, 44, -3, 2),
+            (-44, -40, -2, 3),
+        ] {
+            let target = TargetArea {
+                x_min,
+                x_max,
+                y_min,
+                y_max,
+            };
+            assert_eq!(
+                count_velocities_analytic(&target),
+                Ok(count_brute_force(&target, 110)),
+                "{:?}",
+                target
+            );
+        }
+
+        // Large targets are counted without simulating each trajectory
+        let target = TargetArea {
+            x_min: 1_000_000,
+            x_max: 1_100_000,
+            y_min: -1_000_000,
+            y_max: -900_000,
+        };
+        assert!(count_velocities_analytic(&target).unwrap() > 100_000 * 100_000);
+    }
+
+    #[test]
+    fn test_trajectory() {
+        let states = trajectory(7, 2).take(3).collect::<Vec<_>>();
+        assert_eq!(
+            states,
+            vec![
+                ProbeState {
+                    step: 1,
+                    x: 7,
+                    y: 2,
+                    vx: 6,
+                    vy: 1
+                },
+                ProbeState {
+                    step: 2,
+                    x: 13,
+                    y: 3,
+                    vx: 5,
+                    vy: 0
+                },
+                ProbeState {
+                    step: 3,
+                    x: 18,
+                    y: 3,
+                    vx: 4,
+                    vy: -1
+                },
+            ]
+        );
+        assert_eq!(trajectory(-2, 0).nth(4).map(|s| (s.x, s.vx)), Some((-3, 0)));
+    }
+
+    #[test]
+    fn test_hitting_velocities() {
+        let hits = hitting_velocities(&EXAMPLE).unwrap();
+        assert_eq!(hits.len(), 112);
+        assert!(hits.contains(&Hit {
+            velocity: (7, 2),
+            step: 7,
+            apex: 3
+        }));
+        assert!(hits.contains(&Hit {
+            velocity: (30, -10),
+            step: 1,
+            apex: 0
+        }));
+    }
+
+    #[test]
+    fn test_plot() {
+        assert_eq!(
+            plot(7, 2, &EXAMPLE),
+            [
+                ".............#....#............",
+                ".......#..............#........",
+                "...............................",
+                "S........................#.....",
+                "...............................",
+                "...............................",
+                "...........................#...",
+                "...............................",
+                "....................TTTTTTTTTTT",
+                "....................TTTTTTTTTTT",
+                "....................TTTTTTTT#TT",
+                "....................TTTTTTTTTTT",
+                "....................TTTTTTTTTTT",
+                "....................TTTTTTTTTTT",
+            ]
+            .join("\n")
+        );
     }
 }
@@ -2,55 +2,653 @@
 //!
 //! This module contains the solution of the [twenty-first day's challenges](https://adventofcode.com/2021/day/21).
 use std::collections::HashMap;
+use std::fmt;
 
-/// Compute the product of dice rolls times score of the losing player.
+/// The errors that can occur when configuring a game of Dirac Dice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameError {
+    /// The board does not have any spaces.
+    EmptyBoard,
+    /// The die does not have any faces.
+    NoFaces,
+    /// The die is not rolled in a turn.
+    NoRolls,
+    /// The target score is reached before the game starts.
+    NoTarget,
+    /// There is nobody to play the game.
+    NoPlayers,
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::EmptyBoard => write!(f, "the board must have at least one space"),
+            GameError::NoFaces => write!(f, "the die must have at least one face"),
+            GameError::NoRolls => write!(f, "the die must be rolled at least once per turn"),
+            GameError::NoTarget => write!(f, "the target score must be at least 1"),
+            GameError::NoPlayers => write!(f, "the game needs at least one player"),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+/// The configuration of a game of Dirac Dice.
 ///
-/// The die always gives the number 1-100 in a row and starts over when reaching 100.
-pub fn day_21_1(start_1: usize, start_2: usize) -> usize {
-    let (mut pos_1, mut pos_2) = (start_1, start_2);
-    let (mut score_1, mut score_2) = (0, 0);
+/// The pawns move clockwise on a circular track with spaces numbered from 1 to `board_size`.
+/// In each turn, a player rolls the die `rolls_per_turn` times, moves forward by the sum of the
+/// rolls and adds the number of the space the pawn lands on to the score. The first player
+/// reaching `target_score` wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiceGame {
+    board_size: usize,
+    die_faces: usize,
+    rolls_per_turn: usize,
+    target_score: usize,
+    players: usize,
+}
+
+/// The state of all players: their positions and scores.
+type State = (Vec<usize>, Vec<usize>);
+
+impl DiceGame {
+    /// The game played with the deterministic 100-sided die in the first part of the puzzle.
+    pub const PRACTICE: DiceGame = DiceGame {
+        board_size: 10,
+        die_faces: 100,
+        rolls_per_turn: 3,
+        target_score: 1000,
+        players: 2,
+    };
 
-    let mut iter = (1..=100).cycle();
-    let mut dice_rolls = 0;
+    /// The game played with the three-sided Dirac die in the second part of the puzzle.
+    pub const DIRAC: DiceGame = DiceGame {
+        board_size: 10,
+        die_faces: 3,
+        rolls_per_turn: 3,
+        target_score: 21,
+        players: 2,
+    };
 
-    loop {
-        // Player one plays
-        for _ in 0..3 {
-            pos_1 += iter.next().unwrap();
+    /// Configure a game, making sure that none of the parameters is zero.
+    pub fn new(
+        board_size: usize,
+        die_faces: usize,
+        rolls_per_turn: usize,
+        target_score: usize,
+        players: usize,
+    ) -> Result<Self, GameError> {
+        let checks = [
+            (board_size, GameError::EmptyBoard),
+            (die_faces, GameError::NoFaces),
+            (rolls_per_turn, GameError::NoRolls),
+            (target_score, GameError::NoTarget),
+            (players, GameError::NoPlayers),
+        ];
+        if let Some((_, error)) = checks.iter().find(|(value, _)| *value == 0) {
+            return Err(*error);
         }
+        Ok(Self {
+            board_size,
+            die_faces,
+            rolls_per_turn,
+            target_score,
+            players,
+        })
+    }
+
+    /// The number of spaces on the board.
+    pub fn board_size(&self) -> usize {
+        self.board_size
+    }
+
+    /// The number of faces of the die.
+    pub fn die_faces(&self) -> usize {
+        self.die_faces
+    }
+
+    /// The number of times the die is rolled in each turn.
+    pub fn rolls_per_turn(&self) -> usize {
+        self.rolls_per_turn
+    }
+
+    /// The score needed to win.
+    pub fn target_score(&self) -> usize {
+        self.target_score
+    }
+
+    /// The number of players.
+    pub fn players(&self) -> usize {
+        self.players
+    }
+
+    /// Make sure that there is one starting position on the board per player.
+    fn check_starts(&self, starts: &[usize]) {
+        assert_eq!(
+            starts.len(),
+            self.players,
+            "Expected one starting position per player!"
+        );
+        assert!(
+            starts
+                .iter()
+                .all(|start| (1..=self.board_size).contains(start)),
+            "Starting positions must be on the board!"
+        );
+    }
 
-        pos_1 %= 10;
-        score_1 += if pos_1 == 0 { 10 } else { pos_1 };
-        dice_rolls += 3;
+    /// Move a pawn from `position` forward by `forward` spaces.
+    fn advance(&self, position: usize, forward: usize) -> usize {
+        (position - 1 + forward) % self.board_size + 1
+    }
 
-        if score_1 >= 1000 {
-            break score_2 * dice_rolls;
+    /// Compute the number of ways in which each sum of the rolls in one turn can be obtained.
+    ///
+    /// The result contains pairs of the sum and its number of combinations, ordered by the sum.
+    pub fn roll_distribution(&self) -> Vec<(usize, usize)> {
+        let mut distribution = vec![1];
+        for _ in 0..self.rolls_per_turn {
+            let mut next = vec![0; distribution.len() + self.die_faces];
+            for (sum, count) in distribution.iter().enumerate() {
+                for face in 1..=self.die_faces {
+                    next[sum + face] += count;
+                }
+            }
+            distribution = next;
         }
+        distribution
+            .into_iter()
+            .enumerate()
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+
+    /// Play the game with a deterministic die.
+    ///
+    /// The die always gives the numbers from 1 to `die_faces` in a row and starts over after
+    /// reaching `die_faces`. Returns the product of the number of dice rolls and the lowest
+    /// score of all players that did not win.
+    pub fn play_deterministic(&self, starts: &[usize]) -> usize {
+        self.check_starts(starts);
+        let mut positions = starts.to_vec();
+        let mut scores = vec![0; self.players];
 
-        // Player two plays
-        for _ in 0..3 {
-            pos_2 += iter.next().unwrap();
+        let mut iter = (1..=self.die_faces).cycle();
+        let mut dice_rolls = 0;
+
+        loop {
+            for player in 0..self.players {
+                let forward = iter.by_ref().take(self.rolls_per_turn).sum();
+                positions[player] = self.advance(positions[player], forward);
+                scores[player] += positions[player];
+                dice_rolls += self.rolls_per_turn;
+
+                if scores[player] >= self.target_score {
+                    let loser_score = scores
+                        .iter()
+                        .enumerate()
+                        .filter(|(p, _)| *p != player)
+                        .map(|(_, score)| *score)
+                        .min()
+                        .unwrap_or(0);
+                    return loser_score * dice_rolls;
+                }
+            }
         }
+    }
+
+    /// Compute the number of universes in which each player wins with the Dirac die, split
+    /// by the turn in which the game ends.
+    ///
+    /// The `t`-th entry contains the wins of each player in turn `t + 1`, where every move of a
+    /// single player counts as one turn.
+    ///
+    /// Instead of tracking all states individually in a vector (which requires a lot of memory),
+    /// the different states and their number of occurrences are tracked. Returns `None` if a
+    /// count does not fit into a `u128`.
+    fn wins_per_turn(&self, starts: &[usize]) -> Option<Vec<Vec<u128>>> {
+        self.check_starts(starts);
+        let mut wins_per_turn = vec![];
 
-        pos_2 %= 10;
-        score_2 += if pos_2 == 0 { 10 } else { pos_2 };
-        dice_rolls += 3;
+        // Track the different states and their counts
+        let mut state_counts: HashMap<State, u128> =
+            vec![((starts.to_vec(), vec![0; self.players]), 1)]
+                .into_iter()
+                .collect();
 
-        if score_2 >= 1000 {
-            break score_1 * dice_rolls;
+        let dice_possibilities = self.roll_distribution();
+
+        'game: loop {
+            for player in 0..self.players {
+                if state_counts.is_empty() {
+                    break 'game;
+                }
+                let mut wins = vec![0; self.players];
+                let mut tmp = HashMap::new();
+                for ((positions, scores), count) in state_counts {
+                    for (forward, possibilities) in dice_possibilities.iter() {
+                        let position = self.advance(positions[player], *forward);
+                        let score = scores[player] + position;
+                        let universes = count.checked_mul(*possibilities as u128)?;
+                        if score >= self.target_score {
+                            wins[player] = universes.checked_add(wins[player])?;
+                        } else {
+                            let mut entry = (positions.clone(), scores.clone());
+                            entry.0[player] = position;
+                            entry.1[player] = score;
+                            let total = tmp.entry(entry).or_insert(0);
+                            *total = universes.checked_add(*total)?;
+                        }
+                    }
+                }
+                state_counts = tmp;
+                wins_per_turn.push(wins);
+            }
         }
+
+        Some(wins_per_turn)
+    }
+
+    /// Compute the number of universes in which each player wins with the Dirac die.
+    ///
+    /// Panics if a count does not fit into a `usize`.
+    pub fn count_wins(&self, starts: &[usize]) -> Vec<usize> {
+        self.wins_per_turn(starts)
+            .and_then(|wins_per_turn| self.total_wins(&wins_per_turn))
+            .and_then(|wins| wins.into_iter().map(|w| usize::try_from(w).ok()).collect())
+            .expect("Too many universes!")
+    }
+
+    /// Sum the wins of each player over all turns, or `None` if a sum overflows.
+    fn total_wins(&self, wins_per_turn: &[Vec<u128>]) -> Option<Vec<u128>> {
+        wins_per_turn
+            .iter()
+            .try_fold(vec![0u128; self.players], |mut total, wins| {
+                for (t, w) in total.iter_mut().zip(wins) {
+                    *t = t.checked_add(*w)?;
+                }
+                Some(total)
+            })
+    }
+
+    /// The probability of a specific sequence of rolls over `turns` turns occurring in
+    /// `count` universes, or `None` if it cannot be represented.
+    ///
+    /// The fraction is reduced turn by turn, so the denominator stays far below the number
+    /// of roll sequences over all turns.
+    fn probability(&self, count: u128, turns: usize) -> Option<Ratio> {
+        let universes_per_turn =
+            (self.die_faces as u128).checked_pow(self.rolls_per_turn as u32)?;
+        (0..turns).try_fold(Ratio::new(count, 1), |ratio, _| {
+            ratio.checked_div_integer(universes_per_turn)
+        })
+    }
+
+    /// Analyze the game with the Dirac die for the given starting positions.
+    ///
+    /// Note that the universe counts weigh all universes equally, while a game ending after
+    /// fewer turns is more likely. The probabilities take this into account by weighing
+    /// each universe with the probability of its sequence of rolls.
+    ///
+    /// Returns `None` if a count does not fit into a `u128` or an exact probability cannot
+    /// be represented by a `Ratio`. With the puzzle's board and die, this happens e.g. for a
+    /// target score of 40.
+    pub fn outcome(&self, starts: &[usize]) -> Option<Outcome> {
+        let wins_per_turn = self.wins_per_turn(starts)?;
+
+        let probabilities = (0..self.players)
+            .map(|player| {
+                wins_per_turn.iter().enumerate().try_fold(
+                    Ratio::new(0, 1),
+                    |total, (turn, wins)| {
+                        total.checked_add(self.probability(wins[player], turn + 1)?)
+                    },
+                )
+            })
+            .collect::<Option<_>>()?;
+
+        let ends_per_turn = wins_per_turn
+            .iter()
+            .map(|wins| wins.iter().try_fold(0u128, |acc, w| acc.checked_add(*w)))
+            .collect::<Option<Vec<_>>>()?;
+        let turns = ends_per_turn
+            .iter()
+            .enumerate()
+            .map(|(turn, ends)| self.probability(*ends, turn + 1))
+            .collect::<Option<Vec<_>>>()?;
+        let expected_turns = ends_per_turn.iter().enumerate().try_fold(
+            Ratio::new(0, 1),
+            |total, (turn, ends)| {
+                let weighted = ends.checked_mul(turn as u128 + 1)?;
+                total.checked_add(self.probability(weighted, turn + 1)?)
+            },
+        )?;
+
+        Some(Outcome {
+            wins: self.total_wins(&wins_per_turn)?,
+            probabilities,
+            turns,
+            expected_turns,
+        })
+    }
+
+    /// Analyze the two-player game with the Dirac die for all pairs of starting positions.
+    ///
+    /// The entry `[i][j]` contains the outcome when the first player starts on space `i + 1`
+    /// and the second one on space `j + 1`. Returns `None` if any outcome cannot be computed.
+    pub fn outcome_table(&self) -> Option<Vec<Vec<Outcome>>> {
+        assert_eq!(self.players, 2, "Tables require exactly two players!");
+        (1..=self.board_size)
+            .map(|start_1| {
+                (1..=self.board_size)
+                    .map(|start_2| self.outcome(&[start_1, start_2]))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Compute the number of universes in which each of two players wins with the Dirac die
+    /// using a memoized recursion, counting in the integer type `T`.
+    ///
+    /// Returns `None` if a count does not fit into `T`. With the puzzle's board and die, the
+    /// counts exceed a `u128` for target scores above 56, e.g. for a target score of 100 they
+    /// are of the order of 2^225 and require a `U256`.
+    pub fn count_wins_memoized<T: UniverseCount>(&self, starts: &[usize]) -> Option<[T; 2]> {
+        assert_eq!(self.players, 2, "The memoized solver requires two players!");
+        self.check_starts(starts);
+        let mut solver = MemoizedSolver {
+            game: self,
+            dice_possibilities: self
+                .roll_distribution()
+                .into_iter()
+                .map(|(forward, possibilities)| {
+                    T::from_count(possibilities as u128)
+                        .map(|possibilities| (forward, possibilities))
+                })
+                .collect::<Option<_>>()?,
+            memo: HashMap::new(),
+        };
+        solver.wins([starts[0] - 1, starts[1] - 1, 0, 0])
     }
 }
 
-type State = ((usize, usize), (usize, usize));
+/// An unsigned integer type in which universes are counted.
+///
+/// Implementing this trait for a wider integer type allows counting the universes of games
+/// with larger target scores.
+pub trait UniverseCount: Copy + Ord {
+    const ZERO: Self;
+    const ONE: Self;
+
+    /// Convert a count, returning `None` if it does not fit.
+    fn from_count(count: u128) -> Option<Self>;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+}
 
-/// Compute the number of universes in which the player winning most matches wins.
+macro_rules! impl_universe_count {
+    ($($t:ty),*) => {
+        $(
+            impl UniverseCount for $t {
+                const ZERO: Self = 0;
+                const ONE: Self = 1;
+
+                fn from_count(count: u128) -> Option<Self> {
+                    Self::try_from(count).ok()
+                }
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$t>::checked_add(self, other)
+                }
+
+                fn checked_mul(self, other: Self) -> Option<Self> {
+                    <$t>::checked_mul(self, other)
+                }
+            }
+        )*
+    };
+}
+
+impl_universe_count!(u64, u128, usize);
+
+/// An unsigned 256-bit integer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct U256 {
+    /// The 64-bit limbs, starting with the most significant one.
+    limbs: [u64; 4],
+}
+
+impl U256 {
+    /// Convert the number to a `u128`, if it fits.
+    pub fn to_u128(self) -> Option<u128> {
+        match self.limbs {
+            [0, 0, high, low] => Some((high as u128) << 64 | low as u128),
+            _ => None,
+        }
+    }
+
+    /// Divide by a small `divisor`, returning the quotient and the remainder.
+    fn div_rem(self, divisor: u64) -> (Self, u64) {
+        let mut remainder = 0u128;
+        let mut quotient = [0; 4];
+        for (q, limb) in quotient.iter_mut().zip(self.limbs) {
+            let dividend = remainder << 64 | limb as u128;
+            *q = (dividend / divisor as u128) as u64;
+            remainder = dividend % divisor as u128;
+        }
+        (U256 { limbs: quotient }, remainder as u64)
+    }
+}
+
+impl From<u128> for U256 {
+    fn from(value: u128) -> Self {
+        U256 {
+            limbs: [0, 0, (value >> 64) as u64, value as u64],
+        }
+    }
+}
+
+impl UniverseCount for U256 {
+    const ZERO: Self = U256 { limbs: [0; 4] };
+    const ONE: Self = U256 {
+        limbs: [0, 0, 0, 1],
+    };
+
+    fn from_count(count: u128) -> Option<Self> {
+        Some(count.into())
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        let mut limbs = [0; 4];
+        let mut carry = 0u128;
+        for i in (0..4).rev() {
+            let sum = self.limbs[i] as u128 + other.limbs[i] as u128 + carry;
+            limbs[i] = sum as u64;
+            carry = sum >> 64;
+        }
+        (carry == 0).then_some(U256 { limbs })
+    }
+
+    fn checked_mul(self, other: Self) -> Option<Self> {
+        // Schoolbook multiplication on the limbs, counted from the least significant one
+        let mut product = [0u64; 8];
+        for i in 0..4 {
+            let mut carry = 0u128;
+            for j in 0..4 {
+                let current = product[i + j] as u128
+                    + self.limbs[3 - i] as u128 * other.limbs[3 - j] as u128
+                    + carry;
+                product[i + j] = current as u64;
+                carry = current >> 64;
+            }
+            product[i + 4] = carry as u64;
+        }
+        if product[4..].iter().any(|limb| *limb != 0) {
+            return None;
+        }
+        Some(U256 {
+            limbs: [product[3], product[2], product[1], product[0]],
+        })
+    }
+}
+
+impl std::fmt::Display for U256 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Split into chunks of 19 decimal digits, starting with the least significant one
+        const CHUNK: u64 = 10_000_000_000_000_000_000;
+        let mut chunks = vec![];
+        let mut rest = *self;
+        loop {
+            let (quotient, remainder) = rest.div_rem(CHUNK);
+            chunks.push(remainder);
+            if quotient == U256::ZERO {
+                break;
+            }
+            rest = quotient;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:019}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// The state of a two-player game from the perspective of the player to move: both
+/// positions (starting from 0) and both scores, starting with those of the player to move.
+type PackedState = [usize; 4];
+
+/// The memoized recursion behind `DiceGame::count_wins_memoized`.
+struct MemoizedSolver<'a, T> {
+    game: &'a DiceGame,
+    dice_possibilities: Vec<(usize, T)>,
+    /// The wins of the player to move and the other player from each state visited so far.
+    ///
+    /// Only the states reachable from the starting positions are stored.
+    memo: HashMap<PackedState, [T; 2]>,
+}
+
+impl<T: UniverseCount> MemoizedSolver<'_, T> {
+    /// Count the universes in which the player to move and the other player win starting
+    /// from `state`, or `None` if a count overflows.
+    fn wins(&mut self, state: PackedState) -> Option<[T; 2]> {
+        if let Some(wins) = self.memo.get(&state) {
+            return Some(*wins);
+        }
+
+        let [position, other_position, score, other_score] = state;
+        let mut wins = [T::ZERO; 2];
+        for i in 0..self.dice_possibilities.len() {
+            let (forward, possibilities) = self.dice_possibilities[i];
+            let position = (position + forward) % self.game.board_size;
+            let score = score + position + 1;
+
+            // The roles of the players swap for the next turn
+            let [other_wins, own_wins] = if score >= self.game.target_score {
+                [T::ZERO, T::ONE]
+            } else {
+                self.wins([other_position, position, other_score, score])?
+            };
+            wins = [
+                wins[0].checked_add(own_wins.checked_mul(possibilities)?)?,
+                wins[1].checked_add(other_wins.checked_mul(possibilities)?)?,
+            ];
+        }
+
+        self.memo.insert(state, wins);
+        Some(wins)
+    }
+}
+
+/// A non-negative fraction in lowest terms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ratio {
+    pub numerator: u128,
+    pub denominator: u128,
+}
+
+impl Ratio {
+    /// Create the fraction `numerator / denominator` in lowest terms.
+    ///
+    /// Panics if the denominator is zero.
+    pub fn new(numerator: u128, denominator: u128) -> Self {
+        assert_ne!(denominator, 0, "Denominator must not be zero!");
+        let divisor = gcd(numerator, denominator);
+        Self {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        }
+    }
+
+    /// Add two fractions, or return `None` if the result or an intermediate value overflows.
+    pub fn checked_add(self, other: Ratio) -> Option<Ratio> {
+        let divisor = gcd(self.denominator, other.denominator);
+        let denominator = (self.denominator / divisor).checked_mul(other.denominator)?;
+        let numerator = self
+            .numerator
+            .checked_mul(other.denominator / divisor)?
+            .checked_add(other.numerator.checked_mul(self.denominator / divisor)?)?;
+        Some(Ratio::new(numerator, denominator))
+    }
+
+    /// Divide the fraction by the `integer`, or return `None` if the denominator overflows.
+    pub fn checked_div_integer(self, integer: u128) -> Option<Ratio> {
+        // Cancelling the common factor keeps the fraction in lowest terms
+        let divisor = gcd(self.numerator, integer);
+        Some(Ratio {
+            numerator: self.numerator / divisor,
+            denominator: self.denominator.checked_mul(integer / divisor)?,
+        })
+    }
+
+    /// Approximate the fraction as floating point number.
+    pub fn to_f64(self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+impl std::fmt::Display for Ratio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// The greatest common divisor of `a` and `b`.
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// The analysis of a game with the Dirac die.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    /// The number of universes in which each player wins.
+    pub wins: Vec<u128>,
+    /// The probability of each player to win.
+    pub probabilities: Vec<Ratio>,
+    /// The distribution of the game length: the `t`-th entry contains the probability that
+    /// the game ends after `t + 1` turns, where every move of a single player counts as one
+    /// turn.
+    pub turns: Vec<Ratio>,
+    /// The expected number of turns until the game ends.
+    pub expected_turns: Ratio,
+}
+
+/// Compute the product of dice rolls times score of the losing player.
 ///
-/// Instead of tracking all states individually in a vector (which requires a lot of memory),
-/// the different states and their number of occurrences are tracked.
+/// The die always gives the number 1-100 in a row and starts over when reaching 100.
+pub fn day_21_1(start_1: usize, start_2: usize) -> usize {
+    DiceGame::PRACTICE.play_deterministic(&[start_1, start_2])
+}
+
+/// Compute the number of universes in which the player winning most matches wins.
 ///
 /// For each turn, of three dice throws, a player creates an additional twenty seven universes. These correspond to the
-/// following sums of dice throws (e.g. forward steps for the player in that turn):
+/// following sums of dice throws (e.g. forward steps for the player in that turn), as computed
+/// by `DiceGame::roll_distribution`:
 ///
 /// | Forard | Dice combinations            |     Number of combinations   |
 /// | :----: | :---------------------:      | :--------------------------: |
@@ -62,66 +660,11 @@ type State = ((usize, usize), (usize, usize));
 /// | 8      |  233 323 332                 |  3                           |
 /// | 9      |  333                         |  1                           |
 pub fn day_21_2(start_1: usize, start_2: usize) -> usize {
-    let mut player_1_wins = 0;
-    let mut player_2_wins = 0;
-
-    // Track the different states and their counts
-    let mut state_counts: HashMap<State, usize> = vec![(((start_1, 0), (start_2, 0)), 1)]
+    DiceGame::DIRAC
+        .count_wins(&[start_1, start_2])
         .into_iter()
-        .collect();
-
-    let dice_possiblities: HashMap<usize, usize> =
-        vec![(3, 1), (4, 3), (5, 6), (6, 7), (7, 6), (8, 3), (9, 1)]
-            .into_iter()
-            .collect();
-
-    loop {
-        // Player 1
-        let mut tmp = HashMap::new();
-        for (((old_pos_1, old_score_1), player_2), count) in state_counts {
-            for (forward, possibilites) in dice_possiblities.iter() {
-                let pos_1 = (old_pos_1 + forward) % 10;
-                let score_1 = if pos_1 == 0 {
-                    old_score_1 + 10
-                } else {
-                    old_score_1 + pos_1
-                };
-                if score_1 >= 21 {
-                    player_1_wins += count * possibilites;
-                } else {
-                    let entry = ((pos_1, score_1), player_2);
-                    *tmp.entry(entry).or_insert(0) += count * possibilites;
-                }
-            }
-        }
-        state_counts = tmp;
-
-        // Player 2
-        let mut tmp = HashMap::new();
-        for ((player_1, (old_pos_2, old_score_2)), count) in state_counts {
-            for (forward, possibilites) in dice_possiblities.iter() {
-                let pos_2 = (old_pos_2 + forward) % 10;
-                let score_2 = if pos_2 == 0 {
-                    old_score_2 + 10
-                } else {
-                    old_score_2 + pos_2
-                };
-                if score_2 >= 21 {
-                    player_2_wins += count * possibilites;
-                } else {
-                    let entry = (player_1, (pos_2, score_2));
-                    *tmp.entry(entry).or_insert(0) += count * possibilites;
-                }
-            }
-        }
-        state_counts = tmp;
-
-        if state_counts.is_empty() {
-            break;
-        }
-    }
-
-    std::cmp::max(player_1_wins, player_2_wins)
+        .max()
+        .unwrap()
 }
 
 #[cfg(test)]
@@ -137,4 +680,225 @@ mod tests {
     fn test_day_21_2() {
         assert_eq!(day_21_2(4, 8), 444356092776315);
     }
+
+    #[test]
+    fn test_roll_distribution() {
+        assert_eq!(
+            DiceGame::DIRAC.roll_distribution(),
+            vec![(3, 1), (4, 3), (5, 6), (6, 7), (7, 6), (8, 3), (9, 1)]
+        );
+        let game = DiceGame {
+            die_faces: 6,
+            rolls_per_turn: 2,
+            ..DiceGame::DIRAC
+        };
+        assert_eq!(game.roll_distribution().len(), 11);
+        assert_eq!(
+            game.roll_distribution()
+                .iter()
+                .map(|(_, c)| c)
+                .sum::<usize>(),
+            36
+        );
+    }
+
+    #[test]
+    fn test_configured_games() {
+        assert_eq!(
+            DiceGame::DIRAC.count_wins(&[4, 8]),
+            vec![444356092776315, 341960390180808]
+        );
+
+        // With a target score of 1, the first player always wins in the first turn
+        let game = DiceGame {
+            target_score: 1,
+            ..DiceGame::DIRAC
+        };
+        assert_eq!(game.count_wins(&[4, 8]), vec![27, 0]);
+        assert_eq!(game.play_deterministic(&[4, 8]), 0);
+
+        // Three players on a board with 5 spaces, each moving by one space per turn
+        let game = DiceGame::new(5, 1, 1, 5, 3).unwrap();
+        // Scores after the first round: 2, 3 and 5, so the third player wins
+        assert_eq!(game.count_wins(&[1, 2, 4]), vec![0, 0, 1]);
+        assert_eq!(game.play_deterministic(&[1, 2, 4]), 2 * 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected one starting position per player!")]
+    fn test_wrong_number_of_players() {
+        DiceGame::DIRAC.count_wins(&[1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Starting positions must be on the board!")]
+    fn test_start_off_the_board() {
+        DiceGame::DIRAC.play_deterministic(&[0, 2]);
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(DiceGame::new(10, 3, 3, 21, 2), Ok(DiceGame::DIRAC));
+        assert_eq!(DiceGame::new(0, 3, 3, 21, 2), Err(GameError::EmptyBoard));
+        assert_eq!(DiceGame::new(10, 0, 3, 21, 2), Err(GameError::NoFaces));
+        assert_eq!(DiceGame::new(10, 3, 0, 21, 2), Err(GameError::NoRolls));
+        assert_eq!(DiceGame::new(10, 3, 3, 0, 2), Err(GameError::NoTarget));
+        assert_eq!(DiceGame::new(10, 3, 3, 21, 0), Err(GameError::NoPlayers));
+    }
+
+    #[test]
+    fn test_outcome() {
+        // A single roll of a two-sided die per turn on a board of size 2: the first player
+        // wins immediately with probability 1/2 by landing on space 2, otherwise both have
+        // a score of 1 and the second player wins with probability 1/2 in the next turn...
+        let game = DiceGame::new(2, 2, 1, 2, 2).unwrap();
+        let outcome = game.outcome(&[1, 1]).unwrap();
+        // ...unless they land on space 1 again, which ends the game in the third turn
+        assert_eq!(outcome.wins, vec![3, 1]);
+        assert_eq!(
+            outcome.turns,
+            vec![Ratio::new(1, 2), Ratio::new(1, 4), Ratio::new(1, 4)]
+        );
+        assert_eq!(
+            outcome.probabilities,
+            vec![Ratio::new(3, 4), Ratio::new(1, 4)]
+        );
+        assert_eq!(outcome.expected_turns, Ratio::new(7, 4));
+
+        let outcome = DiceGame::DIRAC.outcome(&[4, 8]).unwrap();
+        assert_eq!(outcome.wins, vec![444356092776315, 341960390180808]);
+        let total = outcome.probabilities[0].to_f64() + outcome.probabilities[1].to_f64();
+        assert!((total - 1.0).abs() < 1e-12);
+        let total = outcome.turns.iter().map(|p| p.to_f64()).sum::<f64>();
+        assert!((total - 1.0).abs() < 1e-12);
+
+        // The roll sequences over all turns exceed a `u128`, but the reduced fractions do not
+        let game = DiceGame {
+            target_score: 30,
+            ..DiceGame::DIRAC
+        };
+        let outcome = game.outcome(&[4, 8]).unwrap();
+        assert!(outcome.wins[0] > u64::MAX as u128);
+        assert_eq!(
+            outcome.probabilities[0].checked_add(outcome.probabilities[1]),
+            Some(Ratio::new(1, 1))
+        );
+
+        let game = DiceGame {
+            target_score: 40,
+            ..DiceGame::DIRAC
+        };
+        assert_eq!(game.outcome(&[4, 8]), None);
+    }
+
+    #[test]
+    fn test_ratio() {
+        assert_eq!(
+            Ratio::new(1, 6).checked_add(Ratio::new(1, 10)),
+            Some(Ratio::new(4, 15))
+        );
+        assert_eq!(
+            Ratio::new(9, 4).checked_div_integer(6),
+            Some(Ratio::new(3, 8))
+        );
+        assert_eq!(Ratio::new(1, u128::MAX).checked_div_integer(2), None);
+        assert_eq!(Ratio::new(3, 4).to_string(), "3/4");
+        assert_eq!(Ratio::new(0, 5), Ratio::new(0, 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Denominator must not be zero!")]
+    fn test_ratio_zero_denominator() {
+        Ratio::new(0, 0);
+    }
+
+    #[test]
+    fn test_outcome_table() {
+        let game = DiceGame {
+            target_score: 10,
+            ..DiceGame::DIRAC
+        };
+        let table = game.outcome_table().unwrap();
+        assert_eq!(table.len(), 10);
+        assert!(table.iter().all(|row| row.len() == 10));
+        assert_eq!(Some(&table[3][1]), game.outcome(&[4, 2]).as_ref());
+    }
+
+    #[test]
+    fn test_count_wins_memoized() {
+        assert_eq!(
+            DiceGame::DIRAC.count_wins_memoized::<u128>(&[4, 8]),
+            Some([444356092776315, 341960390180808])
+        );
+
+        let game = DiceGame::new(7, 4, 2, 15, 2).unwrap();
+        for start_1 in 1..=7 {
+            for start_2 in 1..=7 {
+                let wins = game.count_wins(&[start_1, start_2]);
+                assert_eq!(
+                    game.count_wins_memoized::<usize>(&[start_1, start_2]),
+                    Some([wins[0], wins[1]])
+                );
+            }
+        }
+
+        // Counts beyond the range of a `u64`
+        let game = DiceGame {
+            target_score: 40,
+            ..DiceGame::DIRAC
+        };
+        assert_eq!(game.count_wins_memoized::<u64>(&[4, 8]), None);
+        let wins = game.count_wins_memoized::<u128>(&[4, 8]).unwrap();
+        assert!(wins[0] > u64::MAX as u128);
+        assert_eq!(
+            game.count_wins_memoized::<U256>(&[4, 8]),
+            Some(wins.map(U256::from))
+        );
+
+        // Counts beyond the range of a `u128`
+        let game = DiceGame {
+            target_score: 100,
+            ..DiceGame::DIRAC
+        };
+        assert_eq!(game.count_wins_memoized::<u128>(&[4, 8]), None);
+        let wins = game.count_wins_memoized::<U256>(&[4, 8]).unwrap();
+        assert_eq!(
+            wins.map(|w| w.to_string()),
+            [
+                "55038535590428753856514661082323914715870927758485665656548544838675",
+                "51795524410781849462644411175358066672985907808217186867610537708264"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_u256() {
+        let max = U256::from(u128::MAX);
+        assert_eq!(max.to_u128(), Some(u128::MAX));
+        assert_eq!(max.to_string(), u128::MAX.to_string());
+        assert_eq!(U256::ZERO.to_string(), "0");
+
+        let two_128 = max.checked_add(U256::ONE).unwrap();
+        assert_eq!(two_128.to_u128(), None);
+        assert_eq!(
+            two_128.to_string(),
+            "340282366920938463463374607431768211456"
+        );
+        assert_eq!(
+            two_128
+                .checked_mul(U256::from(1 << 127))
+                .unwrap()
+                .to_string(),
+            "57896044618658097711785492504343953926634992332820282019728792003956564819968"
+        );
+        assert_eq!(two_128.checked_mul(two_128), None);
+        assert_eq!(
+            U256::from(123456789)
+                .checked_mul(U256::from(987654321))
+                .unwrap()
+                .to_u128(),
+            Some(123456789 * 987654321)
+        );
+        assert!(two_128 > max);
+    }
 }
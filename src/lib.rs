@@ -4,7 +4,7 @@
 //!
 //! This module contains the general utilities that are not associated to
 //! the challenge of a particular day, such as reading input data from a
-//! file
+//! file or generating pseudo-random test data
 
 use std::fs::File;
 use std::io::{self, prelude::*, BufReader};
@@ -48,3 +48,30 @@ where
 
     Ok(vec)
 }
+
+/// A linear congruential generator for reproducible pseudo-random numbers.
+///
+/// It is meant for generating test data, not for anything requiring good randomness.
+#[derive(Debug, Clone)]
+pub struct Lcg {
+    state: u64,
+}
+
+impl Lcg {
+    /// Create a generator whose sequence of numbers is determined by the `seed`.
+    pub fn new(seed: u64) -> Self {
+        Lcg { state: seed }
+    }
+
+    /// A pseudo-random number below `bound`.
+    ///
+    /// Panics if `bound` is zero.
+    pub fn next_below(&mut self, bound: usize) -> usize {
+        assert_ne!(bound, 0, "The bound must not be zero!");
+        self.state = self
+            .state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.state >> 33) as usize % bound
+    }
+}
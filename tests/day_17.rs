@@ -1,7 +1,10 @@
+use aoc2021::{self, read_data};
+
 #[test]
 fn test_day_17() {
-    let task_1 = aoc2021::day_17::day_17_1(195, 238, -93, -67);
+    let data = read_data::<String, _>("data/day17.txt").unwrap();
+    let task_1 = aoc2021::day_17::day_17_1(&data);
     assert_eq!(task_1, 4278);
-    let task_2 = aoc2021::day_17::day_17_2(195, 238, -93, -67);
+    let task_2 = aoc2021::day_17::day_17_2(&data);
     assert_eq!(task_2, 1994);
 }